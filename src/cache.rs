@@ -0,0 +1,449 @@
+use crate::hash::Sha256;
+use crate::output::Event;
+use crate::toolchain;
+
+use std::env;
+use std::fs;
+use std::io::Result;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+#[cfg(windows)]
+use std::os::windows::process::ExitStatusExt;
+use std::path::Path;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Output;
+use std::time::SystemTime;
+use std::vec::Vec;
+
+// Bumped whenever the key or the entry layout changes, so old entries are
+// never mistaken for new ones.
+const KEY_VERSION: &str = "gojo-cache-2";
+
+const HIT: u8 = b'h';
+const MISS: u8 = b'm';
+const UNCACHEABLE: u8 = b'u';
+
+pub fn dir() -> String {
+  let home = env::home_dir().map(|home| home.to_string_lossy().to_string()).unwrap_or_default();
+  format!("{home}/.gojo/cache")
+}
+
+// The value for CMAKE_<LANG>_COMPILER_LAUNCHER, which puts gojo in front of
+// every compile CMake runs.
+pub fn launcher(project_root: &str) -> Result<String> {
+  Ok(format!("{};cache;compile;{project_root}", env::current_exe()?.to_string_lossy()))
+}
+
+// Entry point for the launcher: runs the compile, forwards its output and
+// returns its exit code.
+pub fn launch(project_root: &str, command: &[String]) -> i32 {
+  match compile(project_root, command) {
+    Ok(output) => {
+      let _ = std::io::stdout().write_all(&output.stdout);
+      let _ = std::io::stderr().write_all(&output.stderr);
+      output.status.code().unwrap_or(1)
+    }
+    Err(e) => {
+      eprintln!("{e}");
+      1
+    }
+  }
+}
+
+// Runs `command`, a compiler invocation, through the cache. The key is the
+// preprocessed source, the compiler binary and the flags that aren't
+// already reflected in the preprocessed source. Anything the key can't
+// describe, like coverage notes or precompiled headers, is compiled as is.
+pub fn compile(project_root: &str, command: &[String]) -> Result<Output> {
+  let Some(args) = Args::parse(command) else {
+    record(UNCACHEABLE);
+    return run(command);
+  };
+
+  // Preprocessing also writes the dependency file the build tool reads, so
+  // it always describes this checkout, hit or miss.
+  let preprocessed = run(&args.preprocess_command(command))?;
+  if !preprocessed.status.success() {
+    record(UNCACHEABLE);
+    return run(command);
+  }
+
+  let key = args.key(command, project_root, &preprocessed.stdout);
+  let entry = format!("{}/{}/{key}", dir(), &key[..2]);
+  if let Ok(stderr) = fs::read(format!("{entry}.stderr")) {
+    if restore(format!("{entry}.o").as_str(), args.object.as_str()).is_ok() {
+      record(HIT);
+      return Ok(Output { status: ExitStatus::from_raw(0), stdout: Vec::new(), stderr });
+    }
+  }
+
+  let output = run(&args.compile_command(command, project_root))?;
+  record(MISS);
+  if output.status.success() {
+    let _ = store(entry.as_str(), args.object.as_str(), &output.stderr);
+  }
+  Ok(output)
+}
+
+fn run(command: &[String]) -> Result<Output> {
+  Command::new(&command[0])
+    .args(&command[1..])
+    .output()
+    .map_err(|e| toolchain::spawn_error(command[0].as_str(), e))
+}
+
+// Entries are written to a temporary file and renamed into place, so
+// concurrent builds never see half of one.
+fn store(entry: &str, object: &str, stderr: &[u8]) -> Result<()> {
+  if let Some(parent) = Path::new(entry).parent() {
+    fs::create_dir_all(parent)?;
+  }
+  let temporary = format!("{entry}.{}.tmp", std::process::id());
+  fs::copy(object, temporary.as_str())?;
+  fs::rename(temporary.as_str(), format!("{entry}.o"))?;
+  fs::write(temporary.as_str(), stderr)?;
+  fs::rename(temporary, format!("{entry}.stderr"))
+}
+
+fn restore(cached: &str, object: &str) -> Result<()> {
+  if let Some(parent) = Path::new(object).parent() {
+    fs::create_dir_all(parent)?;
+  }
+  let temporary = format!("{object}.{}.tmp", std::process::id());
+  fs::copy(cached, temporary.as_str())?;
+  fs::rename(temporary, object)?;
+  // Eviction goes by modification time, a hit makes the entry recent.
+  fs::File::options().write(true).open(cached)?.set_modified(SystemTime::now())
+}
+
+// Each outcome appends a byte, which is safe from parallel compiles
+// without any locking. `trim` folds the bytes into running totals.
+fn record(outcome: u8) {
+  let _ = fs::create_dir_all(dir());
+  if let Ok(mut file) = fs::File::options().create(true).append(true).open(format!("{}/stats", dir())) {
+    let _ = file.write_all(&[outcome]);
+  }
+}
+
+// Options whose value is the next argument.
+const VALUE_OPTIONS: [&str; 14] = [
+  "-o", "-MF", "-MT", "-MQ", "-I", "-D", "-U", "-isystem", "-iquote", "-idirafter", "-include", "-imacros", "-x", "-Xclang",
+];
+
+// Options that only affect preprocessing, whose effect is already in the
+// preprocessed source.
+const PREPROCESSOR_OPTIONS: [&str; 8] = ["-I", "-D", "-U", "-isystem", "-iquote", "-idirafter", "-include", "-imacros"];
+
+struct Args {
+  source: String,
+  object: String,
+  // -MD or -MMD without -MF or -MT, the defaults depend on -o, which
+  // preprocessing drops.
+  missing_depfile: bool,
+  missing_dep_target: bool,
+  debug: bool,
+}
+
+impl Args {
+  fn parse(command: &[String]) -> Option<Args> {
+    let mut sources: Vec<&str> = Vec::new();
+    let mut object: Option<&str> = None;
+    let mut compile_only = false;
+    let mut depfile = false;
+    let mut has_depfile = false;
+    let mut has_dep_target = false;
+    let mut debug = false;
+
+    let mut i = 1;
+    while i < command.len() {
+      let arg = command[i].as_str();
+      let value = command.get(i + 1).map(|value| value.as_str());
+      if VALUE_OPTIONS.contains(&arg) {
+        let value = value?;
+        match arg {
+          "-o" => object = Some(value),
+          "-MF" => has_depfile = true,
+          "-MT" | "-MQ" => has_dep_target = true,
+          // Precompiled headers aren't part of the preprocessed source.
+          "-include" if Path::new(format!("{value}.gch").as_str()).exists() || Path::new(format!("{value}.pch").as_str()).exists() => return None,
+          "-x" if value.ends_with("-header") => return None,
+          "-Xclang" if value == "-include-pch" => return None,
+          _ => {}
+        }
+        i += 2;
+        continue;
+      }
+      match arg {
+        "-c" => compile_only = true,
+        "-MD" | "-MMD" => depfile = true,
+        "-g0" => debug = false,
+        // Outputs besides the object, or inputs besides the source.
+        "-E" | "-S" | "-M" | "-MM" | "--coverage" | "-ftest-coverage" | "-fprofile-arcs" | "-fcoverage-mapping" | "-ftime-trace"
+        | "-gsplit-dwarf" | "-include-pch" | "-" => return None,
+        _ if arg.starts_with("-fprofile-") || arg.starts_with("-save-temps") || arg.starts_with('@') => return None,
+        _ if arg.starts_with("-x") && arg.ends_with("-header") => return None,
+        _ if arg.starts_with("-g") => debug = true,
+        _ if !arg.starts_with('-') => sources.push(arg),
+        _ => {}
+      }
+      i += 1;
+    }
+
+    let object = object?;
+    if !compile_only || sources.len() != 1 || object.ends_with(".gch") || object.ends_with(".pch") {
+      return None;
+    }
+    Some(Args {
+      source: sources[0].to_string(),
+      object: object.to_string(),
+      missing_depfile: depfile && !has_depfile,
+      missing_dep_target: depfile && !has_dep_target,
+      debug,
+    })
+  }
+
+  fn preprocess_command(&self, command: &[String]) -> Vec<String> {
+    let mut preprocess: Vec<String> = Vec::new();
+    let mut args = command.iter();
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "-c" => {}
+        "-o" => {
+          args.next();
+        }
+        _ => preprocess.push(arg.clone()),
+      }
+    }
+    preprocess.push(String::from("-E"));
+    if self.missing_depfile {
+      let stem = self.object.strip_suffix(".o").unwrap_or(self.object.as_str());
+      preprocess.extend([String::from("-MF"), format!("{stem}.d")]);
+    }
+    if self.missing_dep_target {
+      preprocess.extend([String::from("-MT"), self.object.clone()]);
+    }
+    preprocess
+  }
+
+  // Debug info records the source paths and the working directory. They are
+  // mapped to paths relative to the project root, so the object is the same
+  // in every checkout. A build directory outside the project maps to `.`.
+  fn compile_command(&self, command: &[String], project_root: &str) -> Vec<String> {
+    let mut compile = command.to_vec();
+    if !self.debug || project_root.is_empty() {
+      return compile;
+    }
+    compile.push(format!("-fdebug-prefix-map={project_root}=."));
+    if let Ok(cwd) = env::current_dir() {
+      if !cwd.starts_with(project_root) {
+        compile.push(format!("-fdebug-prefix-map={}=.", cwd.to_string_lossy()));
+      }
+    }
+    compile
+  }
+
+  fn key(&self, command: &[String], project_root: &str, preprocessed: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(KEY_VERSION.as_bytes());
+    hasher.update(compiler_identity(command[0].as_str()).as_bytes());
+
+    let mut args = command[1..].iter();
+    while let Some(arg) = args.next() {
+      let arg = arg.as_str();
+      if ["-o", "-MF", "-MT", "-MQ"].contains(&arg) || PREPROCESSOR_OPTIONS.contains(&arg) {
+        args.next();
+        continue;
+      }
+      let joined = PREPROCESSOR_OPTIONS.iter().any(|option| option.len() == 2 && arg.starts_with(option));
+      if joined || ["-c", "-MD", "-MMD", "-MP"].contains(&arg) || arg == self.source {
+        continue;
+      }
+      hasher.update(arg.as_bytes());
+      hasher.update(b"\0");
+    }
+
+    // The compile maps paths in debug info, see `compile_command`.
+    if self.debug {
+      hasher.update(b"-fdebug-prefix-map\0");
+    }
+
+    // The project root only shows up in line markers, which are made
+    // relative so other checkouts of the project share entries. A root
+    // expanded from __FILE__ stays in the key.
+    let root = format!("\"{project_root}/");
+    for line in preprocessed.split(|byte| *byte == b'\n') {
+      let text = String::from_utf8_lossy(line);
+      if text.starts_with('#') && !project_root.is_empty() {
+        hasher.update(text.replace(root.as_str(), "\"<root>/").as_bytes());
+      } else {
+        hasher.update(line);
+      }
+      hasher.update(b"\n");
+    }
+    hasher.hex()
+  }
+}
+
+// The compiler binary's resolved path, size and modification time, so an
+// upgraded compiler doesn't reuse objects from the old one.
+fn compiler_identity(compiler: &str) -> String {
+  let mut path = Path::new(compiler).to_path_buf();
+  if !compiler.contains('/') {
+    if let Some(found) = toolchain::find_program(compiler) {
+      path = found;
+    }
+  }
+  let path = fs::canonicalize(path.as_path()).unwrap_or(path);
+  let Ok(metadata) = fs::metadata(path.as_path()) else {
+    return compiler.to_string();
+  };
+  let modified = metadata
+    .modified()
+    .ok()
+    .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+    .map(|time| time.as_nanos())
+    .unwrap_or(0);
+  format!("{}\0{}\0{}\0", path.to_string_lossy(), metadata.len(), modified)
+}
+
+struct Entry {
+  path: String,
+  size: u64,
+  modified: SystemTime,
+}
+
+fn entries() -> Vec<Entry> {
+  let mut entries: Vec<Entry> = Vec::new();
+  let Ok(buckets) = fs::read_dir(dir()) else {
+    return entries;
+  };
+  for bucket in buckets.flatten().filter(|bucket| bucket.path().is_dir()) {
+    for file in fs::read_dir(bucket.path()).into_iter().flatten().flatten() {
+      let path = file.path().to_string_lossy().to_string();
+      let Some(entry) = path.strip_suffix(".o") else {
+        continue;
+      };
+      let Ok(metadata) = file.metadata() else {
+        continue;
+      };
+      let stderr_size = fs::metadata(format!("{entry}.stderr")).map(|m| m.len()).unwrap_or(0);
+      entries.push(Entry {
+        path: entry.to_string(),
+        size: metadata.len() + stderr_size,
+        modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+      });
+    }
+  }
+  entries
+}
+
+// Hits, misses and uncacheable compiles so far: the totals `trim` saved plus
+// the outcomes recorded since.
+fn outcomes() -> (u64, u64, u64) {
+  tally(&fs::read(format!("{}/stats", dir())).unwrap_or_default(), saved_outcomes())
+}
+
+fn saved_outcomes() -> (u64, u64, u64) {
+  let totals = fs::read_to_string(format!("{}/stats.totals", dir())).unwrap_or_default();
+  let mut totals = totals.split_whitespace().map(|count| count.parse::<u64>().unwrap_or(0));
+  (totals.next().unwrap_or(0), totals.next().unwrap_or(0), totals.next().unwrap_or(0))
+}
+
+fn tally(log: &[u8], (mut hits, mut misses, mut uncacheable): (u64, u64, u64)) -> (u64, u64, u64) {
+  for outcome in log {
+    match *outcome {
+      HIT => hits += 1,
+      MISS => misses += 1,
+      UNCACHEABLE => uncacheable += 1,
+      _ => {}
+    }
+  }
+  (hits, misses, uncacheable)
+}
+
+// Folds the recorded outcomes into the saved totals, so the log only holds
+// the compiles since the last build. The log is moved aside first, compiles
+// that finish meanwhile start a new one.
+fn fold_outcomes() -> Result<()> {
+  let log = format!("{}/stats", dir());
+  let folding = format!("{log}.{}.tmp", std::process::id());
+  match fs::rename(log.as_str(), folding.as_str()) {
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+    result => result?,
+  }
+  let (hits, misses, uncacheable) = tally(&fs::read(folding.as_str()).unwrap_or_default(), saved_outcomes());
+  fs::write(folding.as_str(), format!("{hits} {misses} {uncacheable}\n"))?;
+  fs::rename(folding, format!("{log}.totals"))
+}
+
+// Evicts the least recently used entries until the cache, its statistics
+// included, fits in `max_bytes`.
+pub fn trim(max_bytes: u64) {
+  let _ = fold_outcomes();
+  let mut entries = entries();
+  let stats_size: u64 = ["stats", "stats.totals"]
+    .iter()
+    .map(|name| fs::metadata(format!("{}/{name}", dir())).map(|m| m.len()).unwrap_or(0))
+    .sum();
+  let mut total: u64 = entries.iter().map(|entry| entry.size).sum::<u64>() + stats_size;
+  if total <= max_bytes {
+    return;
+  }
+  entries.sort_by_key(|entry| entry.modified);
+  for entry in entries {
+    if total <= max_bytes {
+      break;
+    }
+    let _ = fs::remove_file(format!("{}.o", entry.path));
+    let _ = fs::remove_file(format!("{}.stderr", entry.path));
+    total -= entry.size;
+  }
+}
+
+pub fn stats(max_bytes: u64) -> Result<()> {
+  let entries = entries();
+  let size: u64 = entries.iter().map(|entry| entry.size).sum();
+  let (hits, misses, uncacheable) = outcomes();
+
+  status!("\x1b[1;35mObject cache\x1b[0m {}\n", dir());
+  status!("  {:<12} {}\n", "entries", entries.len());
+  status!("  {:<12} {} of {}\n", "size", format_size(size), format_size(max_bytes));
+  if hits + misses > 0 {
+    status!("  {:<12} {} ({:.1}%)\n", "hits", hits, hits as f64 * 100.0 / (hits + misses) as f64);
+  } else {
+    status!("  {:<12} {}\n", "hits", hits);
+  }
+  status!("  {:<12} {}\n", "misses", misses);
+  status!("  {:<12} {}\n\n", "uncacheable", uncacheable);
+
+  Event::new("cache-stats")
+    .with("dir", dir())
+    .with("entries", entries.len())
+    .with("size", size)
+    .with("max_size", max_bytes)
+    .with("hits", hits)
+    .with("misses", misses)
+    .with("uncacheable", uncacheable)
+    .emit();
+  Ok(())
+}
+
+pub fn clear() -> Result<()> {
+  let entries = entries();
+  let size: u64 = entries.iter().map(|entry| entry.size).sum();
+  match fs::remove_dir_all(dir()) {
+    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+    _ => {}
+  }
+  status!("\x1b[1;32mCleared\x1b[0m {} entries ({})\n\n", entries.len(), format_size(size));
+  Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+  if bytes >= 1024 * 1024 * 1024 {
+    return format!("{:.2} GiB", bytes as f64 / (1024.0 * 1024.0 * 1024.0));
+  }
+  format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
@@ -0,0 +1,70 @@
+use crate::json;
+
+use std::env;
+use std::fs;
+use std::io::Result;
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
+#[cfg(windows)]
+use std::os::windows::fs::symlink_file as symlink;
+use std::path::Path;
+
+const GENERATED_HEADER: &str = "# Generated by gojo from .gojo, local edits are overwritten.";
+
+// Points `compile_commands.json` at the project root to the database of the
+// build that ran last, so clangd follows whichever build mode is active. A
+// regular file there is assumed to be the user's own and left alone.
+pub fn link_compile_commands(project_root: &str, build_dir: &str) -> Result<()> {
+  let target = format!("{build_dir}/compile_commands.json");
+  if !fs::exists(target.as_str())? {
+    return Ok(());
+  }
+  let link = format!("{project_root}/compile_commands.json");
+  match fs::symlink_metadata(link.as_str()) {
+    Ok(metadata) if metadata.file_type().is_symlink() => {
+      if fs::read_link(link.as_str())? == Path::new(target.as_str()) {
+        return Ok(());
+      }
+      fs::remove_file(link.as_str())?;
+    }
+    Ok(_) => return Ok(()),
+    Err(_) => {}
+  }
+  symlink(target, link)
+}
+
+// The source files in the compilation database of `build_dir`, each once.
+pub fn database_sources(build_dir: &str) -> Vec<String> {
+  let Some(database) = fs::read_to_string(format!("{build_dir}/compile_commands.json")).ok().and_then(|text| json::parse(text.as_str())) else {
+    return Vec::new();
+  };
+  let mut sources: Vec<String> = Vec::new();
+  for entry in database.as_array().into_iter().flatten() {
+    if let Some(file) = entry.get("file").and_then(|file| file.as_str()) {
+      if !sources.iter().any(|source| source == file) {
+        sources.push(file.to_string());
+      }
+    }
+  }
+  sources
+}
+
+// Writes a `.clangd` with the flags clangd needs for files that aren't in the
+// compilation database, like headers and new sources.
+pub fn write_config(project_root: &str, std: &str) -> Result<()> {
+  let path = format!("{project_root}/.clangd");
+  let existing = fs::read_to_string(path.as_str()).ok();
+  if existing.as_ref().is_some_and(|contents| !contents.starts_with(GENERATED_HEADER)) {
+    return Ok(());
+  }
+
+  let mut flags = vec![format!("-std=c++{std}"), format!("-I{project_root}/src")];
+  if let Some(home) = env::home_dir() {
+    flags.push(format!("-I{}/.gojo/include", home.to_string_lossy()));
+  }
+  let contents = format!("{GENERATED_HEADER}\nCompileFlags:\n  Add: [{}]\n", flags.join(", "));
+  if existing.as_deref() == Some(contents.as_str()) {
+    return Ok(());
+  }
+  fs::write(path, contents)
+}
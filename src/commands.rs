@@ -1,11 +1,39 @@
 use crate::plaintext;
 use crate::templates;
 use crate::packages;
+use crate::package;
+use crate::pgo;
+use crate::profile;
+use crate::runconfig;
+use crate::clangd;
+use crate::cache;
+use crate::coverage;
+use crate::crash;
+use crate::debug;
+use crate::deploy;
+use crate::executables;
+use crate::ctest;
+use crate::diagnostics;
+use crate::gtest;
+use crate::memcheck;
+use crate::fingerprint;
+use crate::hook;
+use crate::native;
+use crate::diagnostics::Severity;
+use crate::output;
+use crate::output::Event;
+use crate::sanitize;
+use crate::testreport;
+use crate::timings;
+use crate::toolchain;
+#[cfg(target_os = "linux")]
+use crate::watch;
+use crate::error;
+use crate::error::GojoError;
 
 use std::collections::hash_map::HashMap;
 use std::fs;
 use std::io::Error;
-use std::io::ErrorKind;
 use std::io::Result;
 use std::vec::Vec;
 use std::path::Path;
@@ -13,8 +41,7 @@ use std::path::PathBuf;
 use std::process::Command;
 use std::process::Stdio;
 use std::time;
-#[allow(unused_imports)]
-use std::os::unix::fs::PermissionsExt;
+use std::time::SystemTime;
 
 extern crate num_cpus;
 
@@ -33,6 +60,35 @@ struct GojoConfig {
   pub cppcheck: bool,
   pub cppcheck_args: String,
   pub quiet: bool,
+  // Minimum coverage percentages enforced by 'gojo coverage'. 0 disables.
+  pub coverage_line_threshold: f64,
+  pub coverage_branch_threshold: f64,
+  // Link-time optimization for release builds.
+  pub lto: bool,
+  // Command 'gojo pgo' trains with, '{bin}' is replaced by the executable.
+  pub pgo_train: String,
+  // 'cmake', or 'native' to drive the compiler directly.
+  pub backend: String,
+  // Raw 'target:' lines for the native backend.
+  pub targets: Vec<String>,
+  // Raw 'pch:' lines, a target followed by the headers to precompile.
+  pub pch: Vec<String>,
+  // 'run.<name>.<field>:' lines as (<name>.<field>, value), see runconfig.
+  pub runs: Vec<(String, String)>,
+  // Sources per unity build batch. 0 disables unity builds.
+  pub unity_batch: usize,
+  // Package metadata 'gojo package' hands to CPack.
+  pub version: String,
+  pub description: String,
+  pub maintainer: String,
+  pub license: String,
+  // Shared object cache in ~/.gojo/cache, and its size limit in MiB.
+  pub cache: bool,
+  pub cache_max_mb: usize,
+  // gdb or lldb for `gojo debug`, whichever is installed when empty.
+  pub debugger: String,
+  // How `run` and `test` get a backtrace from a crash: off, rerun or core.
+  pub backtrace: String,
 }
 
 const DEFAULT_BUILD_DIR: &'static str = "build";
@@ -40,10 +96,9 @@ const CONFIG_FILE: &'static str = ".gojo";
 
 pub fn init(args: &[String]) -> Result<()> {
   if args.is_empty() {
-    return Err(Error::new(
-      ErrorKind::Other,
-      "\x1b[31mincorrect usage:\x1b[0m\n\tgojo init <name> [options]\n\tsee 'gojo help init'\n",
-    ));
+    return Err(GojoError::Usage(
+      "\x1b[31mincorrect usage:\x1b[0m\n\tgojo init <name> [options]\n\tsee 'gojo help init'\n".to_string(),
+    ).into());
   }
 
   let name = args[0].as_str();
@@ -57,6 +112,7 @@ pub fn init(args: &[String]) -> Result<()> {
   let mut hdr_extension: Option<&str> = None;
   let mut build_dir: Option<String> = None;
   let mut create_tests = true;
+  let mut backend = "cmake";
   let mut quiet = false;
 
   const CXX_STDS: &[&str] = &["11", "14", "17", "20", "23"];
@@ -64,44 +120,48 @@ pub fn init(args: &[String]) -> Result<()> {
   const CXX_HDR_EXTENSIONS: &[&str] = &["h", "hpp", "hxx", "h++"];
   const CMAKE_DEFAULT_VERSION: &str = "3.28";
 
-  let arg_map = parse_arguments(&args[1..]);
+  let arg_map = parse_arguments(&args[1..], &["--std", "--src-extension", "-s", "--hdr-extension", "-h", "--build-dir", "-b", "--backend"])?;
   for (flag, val) in arg_map {
     match flag {
       "--std" => {
         if val.is_none() || !(CXX_STDS.contains(&val.unwrap())) {
-          return Err(Error::new(
-            ErrorKind::Other,
-            "\x1b[31mincorrect usage:\x1b[0m\n\tunrecognized value for --std flag\n\tsee 'gojo init --help'\n"));
+          return Err(GojoError::Usage(
+            "\x1b[31mincorrect usage:\x1b[0m\n\tunrecognized value for --std flag\n\tsee 'gojo init --help'\n".to_string()).into());
         }
         std = Some(val.unwrap());
       }
       "--src-extension" | "-s" => {
         if val.is_none() || !(CXX_SRC_EXTENSIONS.contains(&val.unwrap())) {
-          return Err(Error::new(
-            ErrorKind::Other,
-            "\x1b[31mincorrect usage:\x1b[0m\n\tunrecognized value for --src-extension flag\n\tsee 'gojo init --help'\n",
-          ));
+          return Err(GojoError::Usage(
+            "\x1b[31mincorrect usage:\x1b[0m\n\tunrecognized value for --src-extension flag\n\tsee 'gojo init --help'\n".to_string(),
+          ).into());
         }
         src_extension = Some(val.unwrap());
       }
       "--hdr-extension" | "-h" => {
         if val.is_none() || !(CXX_HDR_EXTENSIONS.contains(&val.unwrap())) {
-          return Err(Error::new(
-            ErrorKind::Other,
-            "\x1b[31mincorrect usage:\x1b[0m\n\tunrecognized value for --header-extension flag\n\tsee 'gojo init --help'\n",
-          ));
+          return Err(GojoError::Usage(
+            "\x1b[31mincorrect usage:\x1b[0m\n\tunrecognized value for --header-extension flag\n\tsee 'gojo init --help'\n".to_string(),
+          ).into());
         }
         hdr_extension = Some(val.unwrap());
       }
       "--build-dir" | "-b" => {
         if val.is_none() {
-          return Err(Error::new(
-            ErrorKind::Other,
-            "\x1b[31mincorrect usage:\x1b[0m\n\tunrecognized value for --build-dr flag\n\tsee 'gojo init --help'\n",
-          ));
+          return Err(GojoError::Usage(
+            "\x1b[31mincorrect usage:\x1b[0m\n\tunrecognized value for --build-dr flag\n\tsee 'gojo init --help'\n".to_string(),
+          ).into());
         }
         build_dir = Some(format!("{}/{}", project_root.as_str(), val.unwrap()));
       }
+      "--backend" => {
+        if val != Some("cmake") && val != Some("native") {
+          return Err(GojoError::Usage(
+            "\x1b[31mincorrect usage:\x1b[0m\n\t--backend must be one of 'cmake' or 'native'\n\tsee 'gojo init --help'\n".to_string(),
+          ).into());
+        }
+        backend = val.unwrap();
+      }
       "--no-test" => {
         create_tests = false;
       }
@@ -113,13 +173,12 @@ pub fn init(args: &[String]) -> Result<()> {
         return Ok(());
       }
       _ => {
-        return Err(Error::new(
-          ErrorKind::Other,
+        return Err(GojoError::Usage(
           format!(
             "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid option '{}'\n\tsee 'gojo init --help'\n",
             flag
           ),
-        ));
+        ).into());
       }
     }
   }
@@ -140,10 +199,14 @@ pub fn init(args: &[String]) -> Result<()> {
   fs::create_dir("src/lib")?;
   fs::create_dir(build.as_str())?;
 
-  fs::write(
-    "CMakeLists.txt",
-    templates::root_cmake_lists_txt(name, std_final, cpp, CMAKE_DEFAULT_VERSION),
-  )?;
+  // The native backend builds this layout without any CMake.
+  let cmake = backend == "cmake";
+  if cmake {
+    fs::write(
+      "CMakeLists.txt",
+      templates::root_cmake_lists_txt(name, std_final, cpp, CMAKE_DEFAULT_VERSION),
+    )?;
+  }
   fs::write(
     format!("src/main.{cpp}"),
     templates::main_src(hpp).as_bytes(),
@@ -156,25 +219,26 @@ pub fn init(args: &[String]) -> Result<()> {
     format!("src/lib/hello_world.{hpp}"),
     templates::lib_hello_world_hdr(hpp).as_bytes(),
   )?;
-  fs::write(
-    "src/lib/CMakeLists.txt",
-    templates::lib_cmake_lists_txt(cpp).as_bytes(),
-  )?;
+  if cmake {
+    fs::write(
+      "src/lib/CMakeLists.txt",
+      templates::lib_cmake_lists_txt(cpp).as_bytes(),
+    )?;
+  }
   fs::write("README.md", templates::readme(name).as_bytes())?;
   fs::write(".clang-tidy", plaintext::CLANG_TIDY.as_bytes())?;
   fs::write(".gitignore", plaintext::GIT_IGNORE.as_bytes())?;
 
-  if quiet {
-    Command::new("git")
-      .args(["init"])
-      .stdout(Stdio::null())
-      .output()?;
-  } else {
-    print!("\n\x1b[0;35mInitializing Git repository...\x1b[0m\n");
-    Command::new("git")
-      .args(["init"])
-      .stdout(Stdio::inherit())
-      .output()?;
+  if !quiet {
+    status!("\n\x1b[0;35mInitializing Git repository...\x1b[0m\n");
+  }
+  let git = Command::new("git")
+    .args(["init"])
+    .stdout(if quiet { Stdio::null() } else { output::child_stdout() })
+    .status()
+    .map_err(|e| toolchain::spawn_error("git", e))?;
+  if !git.success() {
+    return Err(GojoError::Failed("\x1b[31mgit init failed\x1b[0m\n\n".to_string()).into());
   }
 
   if create_tests {
@@ -183,10 +247,12 @@ pub fn init(args: &[String]) -> Result<()> {
       format!("test/hello_world_test.{cpp}"),
       templates::test_hello_world_src(hpp).as_bytes(),
     )?;
-    fs::write(
-      "test/CMakeLists.txt",
-      templates::test_cmake_lists_txt(cpp).as_bytes(),
-    )?;
+    if cmake {
+      fs::write(
+        "test/CMakeLists.txt",
+        templates::test_cmake_lists_txt(cpp).as_bytes(),
+      )?;
+    }
   }
 
   if quiet {
@@ -204,7 +270,9 @@ pub fn init(args: &[String]) -> Result<()> {
       cpplint_args: String::new(),
       cppcheck: true,
       cppcheck_args: String::new(),
-      quiet: false
+      quiet: false,
+      backend: backend.to_string(),
+      ..config_default()
     };
 
     config_write(config)?;
@@ -223,48 +291,69 @@ pub fn init(args: &[String]) -> Result<()> {
       cpplint_args: String::new(),
       cppcheck: true,
       cppcheck_args: String::new(),
-      quiet: false
+      quiet: false,
+      backend: backend.to_string(),
+      ..config_default()
     };
 
     config_write(config)?;
-    print!("\n\x1b[1;32mCreated gojo project:\x1b[0m {}\n\t\x1b[1;35mroot:\x1b[0m {}\n\t\x1b[1;35mconfig:\x1b[0m {}/.gojo\n", name, project_root.as_str(), project_root.as_str());
+    status!("\n\x1b[1;32mCreated gojo project:\x1b[0m {}\n\t\x1b[1;35mroot:\x1b[0m {}\n\t\x1b[1;35mconfig:\x1b[0m {}/.gojo\n", name, project_root.as_str(), project_root.as_str());
   }
   Ok(())
 }
 
 pub fn build(args: &[String]) -> Result<()> {
-  let mut build_type = "-DCMAKE_BUILD_TYPE=Debug";
-  let mut test = "-DBUILD_TESTING=OFF";
+  let mut release = false;
+  let mut tests = false;
   let mut clean_build = false;
   let mut quiet = false;
+  let mut verbose = false;
+  let mut timings = false;
+  let mut pch = true;
+  let mut unity = true;
+  let mut sanitizers: Vec<String> = Vec::new();
 
-  let arg_map = parse_arguments(&args);
-  for (flag, _) in arg_map {
+  let arg_map = parse_arguments(&args, &["--sanitize"])?;
+  for (flag, val) in arg_map {
     match flag {
       "--release" | "-r" => {
-        build_type = "-DCMAKE_BUILD_TYPE=Release";
+        release = true;
       }
       "--tests" | "-t" => {
-        test = "-DBUILD_TESTING=ON";
+        tests = true;
       }
       "--clean" | "-c" => {
         clean_build = true;
       }
-      "--quiet | -q" => {
+      "--quiet" | "-q" => {
         quiet = true;
       }
+      "--verbose" | "-v" => {
+        verbose = true;
+      }
+      "--timings" => {
+        timings = true;
+      }
+      "--no-pch" => {
+        pch = false;
+      }
+      "--no-unity" => {
+        unity = false;
+      }
+      "--sanitize" => {
+        sanitizers = sanitize::parse(val.unwrap_or(""))?;
+      }
       "--help" => {
         println!("Oops! This command hasn't been implemented yet...");
         return Ok(());
       }
       _ => {
-        return Err(Error::new(
-          ErrorKind::Other,
+        return Err(GojoError::Usage(
           format!(
             "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid option '{}'\n\tsee 'gojo build --help'\n",
             flag
           ),
-        ));
+        ).into());
       }
     }
   }
@@ -273,159 +362,1171 @@ pub fn build(args: &[String]) -> Result<()> {
     clean()?;
   }
 
-  let config_result = config_read();
-  let mut build_dir = String::from(DEFAULT_BUILD_DIR);
-  let mut name = String::from("project");
-  if config_result.is_some() {
-    let config = config_result.unwrap();
-    build_dir = config.build_dir;
-    name = config.name;
+  let config = config_read().unwrap_or_else(config_default);
+  sanitize::check_supported(&sanitizers)?;
+  let build_dir = sanitize::build_dir(config.build_dir.as_str(), &sanitizers);
+
+  let mut options = BuildOptions::new(build_dir.as_str());
+  options.release = release;
+  options.lto = release && config.lto;
+  options.tests = tests;
+  options.quiet = quiet;
+  options.verbose = verbose;
+  options.pch = pch;
+  options.unity = unity;
+  // Clang can trace where each translation unit spends its time.
+  if timings && toolchain::is_clang() {
+    options.cxx_flags.push(String::from("-ftime-trace"));
   }
+  options.sanitize(&sanitizers);
+  let before = timings.then(|| timings::snapshot(build_dir.as_str()));
+  build_project(&config, &options)?;
 
-  if !fs::exists(build_dir.as_str())? {
-    fs::create_dir(build_dir.as_str())?;
+  if let Some(before) = before {
+    timings::report(build_dir.as_str(), &before)?;
   }
+  Ok(())
+}
 
-  let num_cores = num_cpus::get().to_string();
+// How to configure and compile the project into one build directory.
+struct BuildOptions {
+  build_dir: String,
+  release: bool,
+  lto: bool,
+  tests: bool,
+  cxx_flags: Vec<String>,
+  link_flags: Vec<String>,
+  // Additional -D definitions passed to CMake.
+  definitions: Vec<String>,
+  // CMake code run once the project's targets exist.
+  hook: Vec<String>,
+  // Only this target and what it depends on, for the CMake backend. The
+  // native backend always builds everything, it is cheap when up to date.
+  target: Option<String>,
+  // Precompiled headers and unity builds from the config.
+  pch: bool,
+  unity: bool,
+  quiet: bool,
+  verbose: bool,
+}
 
-  if quiet {
-    let result = Command::new("cmake")
-    .args([test, build_type, "-S", ".", "-B", build_dir.as_str()])
-    .stdout(Stdio::null())
-    .stderr(Stdio::inherit())
-    .output()?;
+impl BuildOptions {
+  fn new(build_dir: &str) -> BuildOptions {
+    BuildOptions {
+      build_dir: build_dir.to_string(),
+      release: false,
+      lto: false,
+      tests: false,
+      cxx_flags: Vec::new(),
+      link_flags: Vec::new(),
+      definitions: Vec::new(),
+      hook: Vec::new(),
+      target: None,
+      pch: true,
+      unity: true,
+      quiet: false,
+      verbose: false,
+    }
+  }
 
-    let status = result.status;
-    if !status.success() {
-      return Err(Error::new(
-          ErrorKind::Other,
-          "\x1b[31mCMake failed to initialize build directory\x1b[0m\n\n"
-      ));
+  fn sanitize(&mut self, sanitizers: &[String]) {
+    if !sanitizers.is_empty() {
+      self.cxx_flags.push(sanitize::compile_flags(sanitizers));
+      self.link_flags.push(sanitize::link_flags(sanitizers));
+    }
+  }
+
+  fn mode(&self) -> &'static str {
+    if self.release {
+      return "Release";
     }
+    "Debug"
+  }
+}
+
+// Configures and compiles the project, streaming progress and summarizing
+// compiler diagnostics.
+fn build_project(config: &GojoConfig, options: &BuildOptions) -> Result<()> {
+  configure_project(config, options)?;
+  compile_project(config, options)
+}
+
+// Creates the build directory and, for the CMake backend, configures it.
+fn configure_project(config: &GojoConfig, options: &BuildOptions) -> Result<()> {
+  let build_dir = options.build_dir.as_str();
+  if !fs::exists(build_dir)? {
+    fs::create_dir_all(build_dir)?;
+  }
+
+  if config.backend == "native" {
+    warn_cmake_only(config, options);
+  } else {
+    // CMake caches the flag variables, so they are always passed to clear a
+    // previous run's.
+    let mut configure_flags = vec![
+      format!("-DBUILD_TESTING={}", if options.tests { "ON" } else { "OFF" }),
+      format!("-DCMAKE_BUILD_TYPE={}", options.mode()),
+      format!("-DCMAKE_INTERPROCEDURAL_OPTIMIZATION={}", if options.lto { "ON" } else { "OFF" }),
+      String::from("-DSTATIC_CHECK=OFF"),
+      format!("-DCMAKE_CXX_FLAGS={}", options.cxx_flags.join(" ")),
+      format!("-DCMAKE_EXE_LINKER_FLAGS={}", options.link_flags.join(" ")),
+      format!("-DCMAKE_SHARED_LINKER_FLAGS={}", options.link_flags.join(" ")),
+    ];
+    configure_flags.extend(options.definitions.iter().cloned());
+
+    let unity = options.unity && config.unity_batch > 0;
+    configure_flags.push(format!("-DCMAKE_UNITY_BUILD={}", if unity { "ON" } else { "OFF" }));
+    if unity {
+      configure_flags.push(format!("-DCMAKE_UNITY_BUILD_BATCH_SIZE={}", config.unity_batch));
+    }
+    let mut hook_code = options.hook.clone();
+    hook_code.extend(hook::examples(config.project_root.as_str(), config.cpp.as_str()));
+    if options.pch {
+      hook_code.extend(hook::precompile_headers(config.project_root.as_str(), &config.pch));
+    }
+    configure_flags.push(format!("-DCMAKE_PROJECT_INCLUDE={}", hook::write(build_dir, &hook_code)?));
+    let launcher = if config.cache { cache::launcher(config.project_root.as_str())? } else { String::new() };
+    configure_flags.push(format!("-DCMAKE_C_COMPILER_LAUNCHER={launcher}"));
+    configure_flags.push(format!("-DCMAKE_CXX_COMPILER_LAUNCHER={launcher}"));
+    configure(build_dir, &configure_flags, options.quiet)?;
+  }
+  Ok(())
+}
 
-    let build_result = Command::new("cmake")
-      .args(["--build", "build", "-j", num_cores.as_str()])
-      .stdout(Stdio::null())
-      .stderr(Stdio::inherit())
-      .output()?;
+// Precompiled headers and unity builds are set up through CMake, the native
+// backend builds without them.
+fn warn_cmake_only(config: &GojoConfig, options: &BuildOptions) {
+  let mut ignored: Vec<&str> = Vec::new();
+  if !config.pch.is_empty() {
+    ignored.push("'pch:' lines");
+  }
+  if config.unity_batch > 0 {
+    ignored.push("'unity_batch'");
+  }
+  if !options.pch {
+    ignored.push("--no-pch");
+  }
+  if !options.unity {
+    ignored.push("--no-unity");
+  }
+  if !ignored.is_empty() {
+    eprint!("\x1b[1;33mwarning:\x1b[0m {} only apply to the 'cmake' backend and are ignored\n\n", ignored.join(", "));
+  }
+}
 
-    let build_status = build_result.status;
-    if !build_status.success() {
-      return Err(Error::new(
-          ErrorKind::Other,
-          "\x1b[31mCMake failed to build project\x1b[0m\n\n"
-      ));
+fn compile_project(config: &GojoConfig, options: &BuildOptions) -> Result<()> {
+  let build_dir = options.build_dir.as_str();
+  let quiet = options.quiet;
+  let verbose = options.verbose;
+  let native = config.backend == "native";
+  if !quiet {
+    status!("\x1b[1;35mCompiling\x1b[0m {} \x1b[1;35min\x1b[0m \x1b[1;36m{}\x1b[0m \x1b[1;35mmode\x1b[0m\n\n", config.name, options.mode());
+  }
+  let start = time::Instant::now();
+  // Compiler output is captured and summarized below. Unless asked for the
+  // raw output, only the progress lines are streamed.
+  let json = output::is_json();
+  let echo = move |line: &str| {
+    if json {
+      if verbose {
+        eprintln!("{line}");
+      }
+    } else if verbose || (!quiet && line.starts_with('[')) {
+      println!("{line}");
+    }
+  };
+  let (success, output) = if native {
+    let mut compile_flags = native::mode_flags(options.release, options.lto);
+    compile_flags.extend(options.cxx_flags.iter().flat_map(|flags| flags.split_whitespace().map(String::from)));
+    let build = native::Build {
+      project_root: config.project_root.clone(),
+      build_dir: build_dir.to_string(),
+      std: config.std.clone(),
+      cpp: config.cpp.clone(),
+      compile_flags,
+      link_flags: options.link_flags.iter().flat_map(|flags| flags.split_whitespace().map(String::from)).collect(),
+      tests: options.tests,
+      cache: config.cache,
+    };
+    native::build(&build, &native_targets(config)?, &echo)?
+  } else {
+    let num_cores = num_cpus::get().to_string();
+    let mut cmake = Command::new("cmake");
+    cmake.args(["--build", build_dir, "-j", num_cores.as_str()]);
+    if let Some(target) = options.target.as_deref() {
+      cmake.args(["--target", target]);
     }
-    return Ok(())
+    let (status, output) = output::capture(&mut cmake, echo)
+    .map_err(|e| toolchain::spawn_error("cmake", e))?;
+    (status.success(), output)
+  };
+  if config.cache {
+    cache::trim(config.cache_max_mb as u64 * 1024 * 1024);
+  }
+  // Editors follow whichever build ran last.
+  clangd::link_compile_commands(config.project_root.as_str(), build_dir)?;
+  clangd::write_config(config.project_root.as_str(), config.std.as_str())?;
+
+  let total_time = start.elapsed();
+  let summary = diagnostics::parse(&output);
+  summary.emit_events();
+  Event::new("build-finished")
+    .with("success", success)
+    .with("duration_ms", total_time.as_millis() as u64)
+    .with("errors", summary.count(Severity::Error))
+    .with("warnings", summary.count(Severity::Warning))
+    .emit();
+  if !json && !verbose && (!quiet || !success) {
+    diagnostics::print_summary(&summary);
+  }
+
+  if !success {
+    let message = if native { "\x1b[31mfailed to build project\x1b[0m\n\n" } else { "\x1b[31mCMake failed to build project\x1b[0m\n\n" };
+    return Err(GojoError::BuildFailed(message.to_string()).into());
+  }
+  if !quiet {
+    status!("\n\x1b[1;32mBuild successful\x1b[0m ({}s)\n\n", total_time.as_secs());
   }
+  Ok(())
+}
+
+// Whether CMake last configured `build_dir` with the tests. The entry is
+// untyped unless the project includes CTest.
+fn tests_configured(build_dir: &str) -> bool {
+  fs::read_to_string(format!("{build_dir}/CMakeCache.txt"))
+    .map(|cache| cache.lines().any(|line| line.starts_with("BUILD_TESTING:") && line.ends_with("=ON")))
+    .unwrap_or(false)
+}
 
-  print!("\n\x1b[0;35mInitliazing CMake in\x1b[0m {}\n", build_dir.as_str());
+// The targets listed in the config, or the ones the init layout implies.
+fn native_targets(config: &GojoConfig) -> Result<Vec<native::Target>> {
+  if config.targets.is_empty() {
+    return Ok(native::infer_targets(config.project_root.as_str(), config.name.as_str(), config.cpp.as_str()));
+  }
+  config.targets.iter().map(|line| native::Target::parse(line)).collect()
+}
+
+// The executables a configured build directory produces.
+fn project_executables(config: &GojoConfig, build_dir: &str) -> Result<Vec<executables::Executable>> {
+  if config.backend == "native" {
+    return Ok(native::executables(build_dir, &native_targets(config)?));
+  }
+  executables::from_codemodel(build_dir)
+}
+
+// Configures `build_dir` with CMake, unless nothing that feeds into the
+// configuration has changed since the last successful run.
+fn configure(build_dir: &str, flags: &[String], quiet: bool) -> Result<()> {
+  let mut args: Vec<String> = flags.to_vec();
+  args.extend([String::from("-S"), String::from("."), String::from("-B"), build_dir.to_string()]);
+
+  let project_root = std::env::current_dir()?.to_string_lossy().to_string();
+  let fingerprint = fingerprint::compute(project_root.as_str(), build_dir, &args);
+  let mut reasons = fingerprint::changes(build_dir, &fingerprint);
+  // `gojo run` finds executables through CMake's file API, a build
+  // directory configured before gojo asked for it has to be redone.
+  executables::request_codemodel(build_dir)?;
+  if reasons.is_empty() && !executables::has_codemodel(build_dir) {
+    reasons.push(String::from("target information from CMake is missing"));
+  }
+  if reasons.is_empty() {
+    if !quiet {
+      status!("\n\x1b[0;35mCMake configuration up to date in\x1b[0m {}\n", build_dir);
+    }
+    Event::new("configure-finished")
+      .with("build_dir", build_dir)
+      .with("success", true)
+      .with("skipped", true)
+      .with("duration_ms", 0u64)
+      .emit();
+    return Ok(());
+  }
+
+  if !quiet {
+    status!("\n\x1b[0;35mInitliazing CMake in\x1b[0m {}\n", build_dir);
+    for reason in reasons.iter() {
+      status!("  \x1b[0;36m-\x1b[0m {}\n", reason);
+    }
+  }
+  Event::new("configure-started")
+    .with("build_dir", build_dir)
+    .with("reasons", reasons)
+    .emit();
+  fingerprint::remove(build_dir);
+
+  let configure_start = time::Instant::now();
   let result = Command::new("cmake")
-    .args([test, build_type, "-S", ".", "-B", build_dir.as_str()])
+    .args(args.as_slice())
     .stdout(Stdio::null())
     .stderr(Stdio::inherit())
-    .output()?;
+    .output()
+    .map_err(|e| toolchain::spawn_error("cmake", e))?;
 
   let status = result.status;
+  Event::new("configure-finished")
+    .with("build_dir", build_dir)
+    .with("success", status.success())
+    .with("skipped", false)
+    .with("duration_ms", configure_start.elapsed().as_millis() as u64)
+    .emit();
   if !status.success() {
-    return Err(Error::new(
-          ErrorKind::Other,
-          "\x1b[31mCMake failed to initialize build directory\x1b[0m\n\n"
-    ));
+    return Err(GojoError::ConfigureFailed(
+          "\x1b[31mCMake failed to initialize build directory\x1b[0m\n\n".to_string()
+    ).into());
   }
+  fingerprint::save(build_dir, &fingerprint)
+}
 
-  let mode: Vec<&str> = build_type.split("=").collect();
-  print!("\x1b[1;35mCompiling\x1b[0m {} \x1b[1;35min\x1b[0m \x1b[1;36m{}\x1b[0m \x1b[1;35mmode\x1b[0m\n\n", name, mode[1]);
-  let start = time::Instant::now();
-  let build_result = Command::new("cmake")
-    .args(["--build", "build", "-j", num_cores.as_str()])
+pub fn run(args: &[String]) -> Result<()> {
+  let config = config_read().unwrap_or_else(config_default);
+
+  // Options before `--` are gojo's, everything after it goes to the program
+  // as it is. Plain words before it are passed along as well.
+  let mut release = false;
+  let mut memcheck = false;
+  let mut run_config: Option<String> = None;
+  let mut bin: Option<String> = None;
+  let mut example: Option<String> = None;
+  let mut sanitizers: Vec<String> = Vec::new();
+  let mut program_args: Vec<String> = Vec::new();
+  let mut iter = args.iter();
+  while let Some(arg) = iter.next() {
+    if arg == "--" {
+      program_args.extend(iter.by_ref().cloned());
+    } else if arg == "--help" {
+      println!("Oops! This command hasn't been implemented yet...");
+      return Ok(());
+    } else if arg == "--release" || arg == "-r" {
+      release = true;
+    } else if arg == "--memcheck" {
+      memcheck = true;
+    } else if arg == "--sanitize" {
+      sanitizers = sanitize::parse(iter.next().map(|val| val.as_str()).unwrap_or(""))?;
+    } else if let Some(val) = arg.strip_prefix("--sanitize=") {
+      sanitizers = sanitize::parse(val)?;
+    } else if arg == "--bin" || arg == "--example" || arg == "--config" {
+      let Some(val) = iter.next() else {
+        return Err(GojoError::Usage(format!(
+          "\x1b[31mincorrect usage:\x1b[0m\n\t'{arg}' requires a name\n\tsee 'gojo run --help'\n"
+        ))
+        .into());
+      };
+      match arg.as_str() {
+        "--bin" => bin = Some(val.clone()),
+        "--example" => example = Some(val.clone()),
+        _ => run_config = Some(val.clone()),
+      }
+    } else if let Some(val) = arg.strip_prefix("--bin=") {
+      bin = Some(val.to_string());
+    } else if let Some(val) = arg.strip_prefix("--example=") {
+      example = Some(val.to_string());
+    } else if let Some(val) = arg.strip_prefix("--config=") {
+      run_config = Some(val.to_string());
+    } else if arg.starts_with('-') {
+      return Err(GojoError::Usage(format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid option '{arg}'\n\tpass arguments for the program after '--', as in 'gojo run -- {arg}'\n"
+      ))
+      .into());
+    } else {
+      program_args.push(arg.clone());
+    }
+  }
+  if bin.is_some() && example.is_some() {
+    return Err(GojoError::Usage(String::from(
+      "\x1b[31mincorrect usage:\x1b[0m\n\t--bin and --example can't be used together\n",
+    ))
+    .into());
+  }
+
+  sanitize::check_supported(&sanitizers)?;
+  let valgrind = if memcheck { Some(memcheck_valgrind(&sanitizers)?) } else { None };
+  let crash_mode = crash::mode(config.backtrace.as_str())?;
+  let dotenv = runconfig::dotenv(config.project_root.as_str())?;
+  // A run configuration's arguments come before the ones on the command
+  // line.
+  let run_config = match run_config.as_deref() {
+    Some(name) => Some(runconfig::find(&config.runs, name, config.project_root.as_str())?),
+    None => None,
+  };
+  if let Some(run_config) = run_config.as_ref() {
+    program_args.splice(0..0, run_config.args.iter().cloned());
+  }
+  let cwd = match run_config.as_ref().and_then(|run_config| run_config.cwd.clone()) {
+    Some(cwd) => cwd,
+    None => std::env::current_dir()?.to_string_lossy().to_string(),
+  };
+  let build_dir = sanitize::build_dir(config.build_dir.as_str(), &sanitizers);
+  let mut options = BuildOptions::new(build_dir.as_str());
+  options.release = release;
+  options.lto = release && config.lto;
+  options.quiet = true;
+  options.sanitize(&sanitizers);
+  configure_project(&config, &options)?;
+
+  let executables = project_executables(&config, build_dir.as_str())?;
+  let executable = executables::select(&executables, bin.as_deref(), example.as_deref(), config.name.as_str())?;
+  options.target = Some(executable.target.clone());
+  compile_project(&config, &options)?;
+
+  let memcheck_dir = format!("{build_dir}/memcheck");
+  let mut program = match valgrind.as_deref() {
+    Some(valgrind) => {
+      let _ = fs::remove_dir_all(memcheck_dir.as_str());
+      fs::create_dir_all(memcheck_dir.as_str())?;
+      let suppressions = memcheck::suppressions(config.project_root.as_str(), build_dir.as_str());
+      let mut program = Command::new(valgrind);
+      program.args(memcheck::options(format!("{memcheck_dir}/run.%p.xml").as_str(), &suppressions)).arg(executable.path.as_str());
+      program
+    }
+    None => Command::new(executable.path.as_str()),
+  };
+  program.args(program_args.as_slice()).current_dir(cwd.as_str());
+  // What the program gets on top of gojo's own environment, kept for the
+  // crash report.
+  let mut env = runconfig::dotenv_additions(&dotenv);
+  let stdin = run_config.as_ref().and_then(|run_config| run_config.stdin.clone());
+  if let Some(run_config) = run_config.as_ref() {
+    env.extend(run_config.env.iter().cloned());
+    if let Some(stdin) = stdin.as_deref() {
+      let file = fs::File::open(stdin).map_err(|e| -> Error {
+        GojoError::Config(format!("\x1b[31mfile not found:\x1b[0m\n\tcan't read stdin for '{}' from {stdin}: {e}\n", run_config.name)).into()
+      })?;
+      program.stdin(file);
+    }
+  }
+  program.envs(env.iter().map(|(var, value)| (var, value)));
+  if !sanitizers.is_empty() {
+    sanitize::set_runtime_options(&mut program, &sanitizers);
+    let (status, report) = sanitize::run_and_scan(&mut program)?;
+    if let Some(report) = report {
+      return Err(sanitize::report_error(report.as_str(), GojoError::Failed));
+    }
+    if !status.success() {
+      return Err(GojoError::exited(status).into());
+    }
+    return Ok(());
+  }
+  if crash_mode == crash::Mode::Core {
+    crash::allow_core_dumps(&mut program);
+  }
+  let started = SystemTime::now();
+  let mut child = program
     .stdout(Stdio::inherit())
     .stderr(Stdio::inherit())
-    .output()?;
+    .spawn()
+    .map_err(|e| toolchain::spawn_error(executable.path.as_str(), e))?;
+  let pid = child.id();
+  let status = child.wait()?;
+  if valgrind.is_some() {
+    memcheck::report(&memcheck::read_reports(memcheck_dir.as_str())?, config.project_root.as_str())?;
+  } else if let Some(signal) = error::signal(&status) {
+    let crash = crash::Crash {
+      name: executable.name.as_str(),
+      program: executable.path.as_str(),
+      args: &program_args,
+      cwd: cwd.as_str(),
+      env: &env,
+      stdin: stdin.as_deref(),
+      signal: Some(signal),
+      pid: Some(pid),
+      started,
+    };
+    crash::report(&crash, crash_mode);
+  }
+  if !status.success() {
+    return Err(GojoError::exited(status).into());
+  }
+  Ok(())
+}
 
-  let total_time = start.elapsed();
-  let build_status = build_result.status;
-  if !build_status.success() {
-    return Err(Error::new(
-          ErrorKind::Other,
-          "\x1b[31mCMake failed to build project\x1b[0m\n\n"
-    ));
-  }
-  print!("\n\x1b[1;32mBuild successful\x1b[0m ({}s)\n\n", total_time.as_secs());
+pub fn debug(args: &[String]) -> Result<()> {
+  let config = config_read().unwrap_or_else(config_default);
+
+  let mut bin: Option<String> = None;
+  let mut example: Option<String> = None;
+  let mut test: Option<String> = None;
+  let mut breakpoints: Vec<String> = Vec::new();
+  let mut program_args: Vec<String> = Vec::new();
+  let mut iter = args.iter();
+  while let Some(arg) = iter.next() {
+    if arg == "--" {
+      program_args.extend(iter.by_ref().cloned());
+      break;
+    }
+    if arg == "--help" {
+      println!("Oops! This command hasn't been implemented yet...");
+      return Ok(());
+    }
+    let (flag, value) = match arg.split_once('=') {
+      Some((flag, value)) => (flag, Some(value.to_string())),
+      None => (arg.as_str(), None),
+    };
+    if !["--bin", "--example", "--test", "--break"].contains(&flag) {
+      return Err(GojoError::Usage(format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid option '{arg}'\n\tpass arguments for the program after '--', as in 'gojo debug -- {arg}'\n"
+      ))
+      .into());
+    }
+    let Some(value) = value.or_else(|| iter.next().cloned()) else {
+      return Err(GojoError::Usage(format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\t'{flag}' requires a value\n\tsee 'gojo debug --help'\n"
+      ))
+      .into());
+    };
+    match flag {
+      "--bin" => bin = Some(value),
+      "--example" => example = Some(value),
+      "--test" => test = Some(value),
+      _ => breakpoints.push(debug::parse_breakpoint(value.as_str())?),
+    }
+  }
+  if example.is_some() && (bin.is_some() || test.is_some()) {
+    return Err(GojoError::Usage(String::from(
+      "\x1b[31mincorrect usage:\x1b[0m\n\t--example can't be used with --bin or --test\n",
+    ))
+    .into());
+  }
+  let debugger = debug::choose(config.debugger.as_str())?;
+
+  // Debug information and no optimizations, whatever the last build was.
+  // Tests that are already built stay on, turning them off would only
+  // configure the tree again for the next `gojo test`.
+  let mut options = BuildOptions::new(config.build_dir.as_str());
+  options.tests = test.is_some() || tests_configured(config.build_dir.as_str());
+  options.quiet = true;
+  configure_project(&config, &options)?;
+
+  let executables = project_executables(&config, config.build_dir.as_str())?;
+  let executable = match test.as_deref() {
+    Some(filter) => {
+      program_args.insert(0, format!("--gtest_filter={filter}"));
+      executables::select_test(&executables, bin.as_deref())?
+    }
+    None => executables::select(&executables, bin.as_deref(), example.as_deref(), config.name.as_str())?,
+  };
+  options.target = Some(executable.target.clone());
+  compile_project(&config, &options)?;
+
+  let cwd = std::env::current_dir()?.to_string_lossy().to_string();
+  status!("\x1b[1;35mDebugging\x1b[0m {} \x1b[1;35mwith\x1b[0m {}\n", executable.name, debugger);
+  let mut command = debug::command(debugger.as_str(), executable.path.as_str(), &program_args, cwd.as_str(), &breakpoints);
+  runconfig::apply_dotenv(&mut command, &runconfig::dotenv(config.project_root.as_str())?);
+  let status = command
+    .stdout(Stdio::inherit())
+    .stdin(Stdio::inherit())
+    .stderr(Stdio::inherit())
+    .status()
+    .map_err(|e| toolchain::spawn_error(debugger.as_str(), e))?;
+  if !status.success() {
+    return Err(GojoError::exited(status).into());
+  }
   Ok(())
 }
 
-pub fn run(args: &[String]) -> Result<()> {
-  let config_result = config_read();
-  let mut build_dir = String::from(DEFAULT_BUILD_DIR);
-  let mut name = String::new();
-  if config_result.is_some() {
-    let config = config_result.unwrap();
-    build_dir = config.build_dir;
-    name = config.name;
+pub fn profile(args: &[String]) -> Result<()> {
+  // Everything after `--` goes to the program as it is.
+  let (args, program_args) = match args.iter().position(|arg| arg == "--") {
+    Some(idx) => (&args[..idx], &args[idx + 1..]),
+    None => (args, &args[args.len()..]),
+  };
+  let mut bin: Option<&str> = None;
+  let mut example: Option<&str> = None;
+  let mut top: usize = 10;
+  let arg_map = parse_arguments(args, &["--bin", "--example", "--top"])?;
+  for (flag, val) in arg_map {
+    match flag {
+      "--bin" => {
+        bin = val;
+      }
+      "--example" => {
+        example = val;
+      }
+      "--top" => {
+        top = match val.and_then(|val| val.parse().ok()) {
+          Some(top) => top,
+          None => {
+            return Err(GojoError::Usage(String::from(
+              "\x1b[31mincorrect usage:\x1b[0m\n\t--top requires a number\n\tsee 'gojo profile --help'\n",
+            ))
+            .into());
+          }
+        };
+      }
+      "--help" => {
+        println!("Oops! This command hasn't been implemented yet...");
+        return Ok(());
+      }
+      _ => {
+        return Err(GojoError::Usage(format!(
+          "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid option '{}'\n\tpass arguments for the program after '--', as in 'gojo profile -- {}'\n",
+          flag, flag
+        ))
+        .into());
+      }
+    }
   }
 
-  if !fs::exists(build_dir.as_str())? {
-    return Err(Error::new(
-      ErrorKind::Other,
-      "\x1b[31mfile not found:\x1b[0m\n\tno build directory discovered for this project\n",
-    ));
+  let config = config_read().unwrap_or_else(config_default);
+  // Optimized like a release build, so the profile shows where release
+  // builds spend their time, but with stacks perf can walk.
+  let build_dir = format!("{}/profile", config.build_dir);
+  let mut options = BuildOptions::new(build_dir.as_str());
+  options.release = true;
+  options.lto = config.lto;
+  options.quiet = true;
+  options.cxx_flags.push(String::from(profile::FLAGS));
+  configure_project(&config, &options)?;
+
+  let executables = project_executables(&config, build_dir.as_str())?;
+  let executable = executables::select(&executables, bin, example, config.name.as_str())?;
+  options.target = Some(executable.target.clone());
+  compile_project(&config, &options)?;
+
+  let data_file = format!("{build_dir}/perf.data");
+  let _ = fs::remove_file(data_file.as_str());
+  profile::record(executable.path.as_str(), program_args, data_file.as_str())?;
+  let stacks = profile::fold(data_file.as_str())?;
+  let folded = format!("{build_dir}/stacks.folded");
+  let svg = format!("{build_dir}/flamegraph.svg");
+  profile::write_folded(&stacks, folded.as_str())?;
+  fs::write(svg.as_str(), profile::flamegraph(&stacks, format!("{} {}", executable.name, program_args.join(" ")).trim_end()))?;
+
+  profile::print_top(&stacks, top);
+  status!("\x1b[1;35mFlame graph:\x1b[0m {}\n\n", svg);
+  Event::new("profile-finished")
+    .with("samples", stacks.values().sum::<u64>())
+    .with("flamegraph", svg.as_str())
+    .with("folded", folded.as_str())
+    .emit();
+  Ok(())
+}
+
+pub fn test(args: &[String]) -> Result<()> {
+  let mut sanitizers: Vec<String> = Vec::new();
+  let mut memcheck = false;
+  let mut list = false;
+  let mut reports: Vec<testreport::Report> = Vec::new();
+  // A plain word is the pattern of test names to run.
+  let mut filter = ctest::Filter::default();
+  let mut iter = args.iter();
+  while let Some(arg) = iter.next() {
+    if arg == "--help" {
+      println!("Oops! This command hasn't been implemented yet...");
+      return Ok(());
+    } else if arg == "--memcheck" {
+      memcheck = true;
+    } else if arg == "--list" {
+      list = true;
+    } else if arg == "--sanitize" {
+      sanitizers = sanitize::parse(iter.next().map(|val| val.as_str()).unwrap_or(""))?;
+    } else if let Some(val) = arg.strip_prefix("--sanitize=") {
+      sanitizers = sanitize::parse(val)?;
+    } else if arg == "--report" {
+      reports.push(testreport::parse(iter.next().map(|val| val.as_str()).unwrap_or(""))?);
+    } else if let Some(val) = arg.strip_prefix("--report=") {
+      reports.push(testreport::parse(val)?);
+    } else if arg == "--exclude" || arg == "--label" {
+      let Some(val) = iter.next() else {
+        return Err(GojoError::Usage(format!(
+          "\x1b[31mincorrect usage:\x1b[0m\n\t'{arg}' requires a regular expression\n\tsee 'gojo test --help'\n"
+        ))
+        .into());
+      };
+      if arg == "--exclude" {
+        filter.exclude = Some(val.clone());
+      } else {
+        filter.label = Some(val.clone());
+      }
+    } else if let Some(val) = arg.strip_prefix("--exclude=") {
+      filter.exclude = Some(val.to_string());
+    } else if let Some(val) = arg.strip_prefix("--label=") {
+      filter.label = Some(val.to_string());
+    } else if arg.starts_with('-') {
+      return Err(GojoError::Usage(format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid option '{arg}'\n\tsee 'gojo test --help'\n"
+      ))
+      .into());
+    } else if let Some(pattern) = filter.pattern.as_ref() {
+      return Err(GojoError::Usage(format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tonly one test pattern can be given, found '{pattern}' and '{arg}'\n\tuse '|' to match either\n"
+      ))
+      .into());
+    } else {
+      filter.pattern = Some(arg.clone());
+    }
+  }
+
+  let config = config_read().unwrap_or_else(config_default);
+  sanitize::check_supported(&sanitizers)?;
+  let crash_mode = crash::mode(config.backtrace.as_str())?;
+  let build_dir = sanitize::build_dir(config.build_dir.as_str(), &sanitizers);
+
+  // The tests are built first, so ctest never runs stale binaries or finds
+  // nothing in a build configured without them.
+  let mut options = BuildOptions::new(build_dir.as_str());
+  options.tests = true;
+  options.quiet = true;
+  options.sanitize(&sanitizers);
+  build_project(&config, &options)?;
+
+  // CMake projects register every GoogleTest case as a test of its own,
+  // the native backend registers whole test binaries. Those get the names
+  // as a --gtest_filter.
+  let native = config.backend == "native";
+  let gtest_filter = if native { gtest::filter(filter.pattern.as_deref(), filter.exclude.as_deref())? } else { None };
+  let filter_args = filter.args(!native);
+  if list {
+    return list_tests(build_dir.as_str(), &filter_args, gtest_filter.as_deref(), native);
   }
 
-  if !args.is_empty() && args[0] == "--help" {
+  let valgrind = if memcheck { Some(memcheck_valgrind(&sanitizers)?) } else { None };
+
+  let mut ctest = Command::new("ctest");
+  ctest.args(["-V"]).args(filter_args.as_slice()).current_dir(build_dir.as_str());
+  if let Some(gtest_filter) = gtest_filter.as_deref() {
+    ctest.env("GTEST_FILTER", gtest_filter);
+  }
+  let env = runconfig::dotenv_additions(&runconfig::dotenv(config.project_root.as_str())?);
+  ctest.envs(env.iter().map(|(var, value)| (var, value)));
+  sanitize::set_runtime_options(&mut ctest, &sanitizers);
+  let memcheck_dir = format!("{build_dir}/memcheck");
+  if let Some(valgrind) = valgrind.as_deref() {
+    let _ = fs::remove_dir_all(memcheck_dir.as_str());
+    fs::create_dir_all(memcheck_dir.as_str())?;
+    let suppressions = memcheck::suppressions(config.project_root.as_str(), build_dir.as_str());
+    let options = memcheck::options(format!("{memcheck_dir}/test.%p.xml").as_str(), &suppressions);
+    ctest.args(["-T", "memcheck", "--overwrite"]).arg(format!("MemoryCheckCommand={valgrind}"));
+    ctest.arg("--overwrite").arg(format!("MemoryCheckCommandOptions={}", memcheck::ctest_options(&options)?));
+  }
+  if crash_mode == crash::Mode::Core {
+    crash::allow_core_dumps(&mut ctest);
+  }
+  // Reports are put together from what ctest and GoogleTest write here.
+  let report_dir = format!("{build_dir}/test-reports");
+  let gtest_dir = format!("{report_dir}/gtest");
+  let junit_file = if !reports.is_empty() && testreport::ctest_writes_junit() {
+    Some(format!("{report_dir}/ctest.xml"))
+  } else {
+    None
+  };
+  if !reports.is_empty() {
+    let _ = fs::remove_dir_all(report_dir.as_str());
+    fs::create_dir_all(gtest_dir.as_str())?;
+    ctest.env("GTEST_OUTPUT", format!("xml:{gtest_dir}/"));
+  }
+  if let Some(junit_file) = junit_file.as_deref() {
+    ctest.args(["--output-junit", junit_file]);
+  }
+
+  // The output is captured to find results, crashes and sanitizer reports.
+  // In JSON mode raw ctest output goes to stderr so stdout only carries
+  // events.
+  let json = output::is_json();
+  if !json {
+    ctest.env("GTEST_COLOR", "1");
+  }
+  let started = SystemTime::now();
+  let (status, output) = output::capture(&mut ctest, move |line| {
+    if json {
+      eprintln!("{line}");
+    } else {
+      println!("{line}");
+    }
+  })
+  .map_err(|e| toolchain::spawn_error("ctest", e))?;
+  let results: Vec<ctest::TestResult> = output.iter().filter_map(|line| ctest::parse_result_line(line)).collect();
+  for result in results.iter() {
+    result.emit_event();
+  }
+  if results.is_empty() && status.success() {
+    return Err(no_tests(&filter, native));
+  }
+  let passed = results.iter().filter(|result| result.passed()).count();
+  Event::new("test-finished")
+    .with("success", status.success())
+    .with("passed", passed)
+    .with("failed", results.len() - passed)
+    .emit();
+
+  let commands = ctest::commands(build_dir.as_str(), &filter_args);
+  if !reports.is_empty() {
+    let cases = testreport::collect(&results, &commands, junit_file.as_deref(), gtest_dir.as_str());
+    for report in reports.iter() {
+      testreport::write(report, &cases)?;
+      status!("\x1b[1;32mWrote test report\x1b[0m {}\n", report.path);
+      Event::new("test-report")
+        .with("format", if report.format == testreport::Format::Junit { "junit" } else { "json" })
+        .with("path", report.path.as_str())
+        .with("tests", cases.len())
+        .emit();
+    }
+  }
+
+  if let Some(report) = output.iter().find_map(|line| sanitize::find_report(line)) {
+    return Err(sanitize::report_error(report.as_str(), GojoError::TestFailed));
+  }
+  if valgrind.is_some() {
+    memcheck::report(&memcheck::read_reports(memcheck_dir.as_str())?, config.project_root.as_str())?;
+  }
+  let crashed: Vec<&ctest::TestResult> = results.iter().filter(|result| result.status == "crashed").collect();
+  if !crashed.is_empty() {
+    for result in crashed {
+      let Some(test) = commands.iter().find(|test| test.name == result.name) else {
+        continue;
+      };
+      let crash = crash::Crash {
+        name: test.name.as_str(),
+        program: test.command[0].as_str(),
+        args: &test.command[1..],
+        cwd: test.cwd.as_str(),
+        env: &env,
+        stdin: None,
+        signal: None,
+        pid: None,
+        started,
+      };
+      crash::report(&crash, crash_mode);
+    }
+  }
+  statusln!();
+  if !status.success() {
+    return Err(tests_failed());
+  }
+  Ok(())
+}
+
+// Prints the tests `gojo test` would run with the same filter, without
+// running them. Whole GoogleTest binaries are asked for their cases.
+fn list_tests(build_dir: &str, filter_args: &[String], gtest_filter: Option<&str>, native: bool) -> Result<()> {
+  let mut names: Vec<String> = Vec::new();
+  for test in ctest::commands(build_dir, filter_args) {
+    if native {
+      names.extend(gtest::list(&test.command, test.cwd.as_str(), gtest_filter));
+    } else {
+      names.push(test.name);
+    }
+  }
+  for name in names.iter() {
+    statusln!("{}", name);
+    Event::new("test-listed").with("name", name.as_str()).emit();
+  }
+  status!("\n\x1b[1;32m{} test(s)\x1b[0m\n", names.len());
+  Ok(())
+}
+
+// Valgrind, for a --memcheck that isn't combined with sanitizers.
+fn memcheck_valgrind(sanitizers: &[String]) -> Result<String> {
+  if !sanitizers.is_empty() {
+    return Err(GojoError::Usage(String::from(
+      "\x1b[31mincorrect usage:\x1b[0m\n\t--memcheck can't be combined with --sanitize\n",
+    ))
+    .into());
+  }
+  memcheck::check_installed()
+}
+
+fn no_tests(filter: &ctest::Filter, native: bool) -> Error {
+  let hint = if filter.pattern.is_some() || filter.exclude.is_some() || filter.label.is_some() {
+    "no test matches the filter, see 'gojo test --list'"
+  } else if native {
+    "put test sources in test/ or add a 'target: test' line to .gojo"
+  } else {
+    "register tests with add_test or gtest_discover_tests in test/CMakeLists.txt"
+  };
+  GojoError::TestFailed(format!("\x1b[31mno tests were found:\x1b[0m\n\t{hint}\n")).into()
+}
+
+fn tests_failed() -> Error {
+  GojoError::TestFailed("\x1b[31mtests failed\x1b[0m\n".to_string()).into()
+}
+
+pub fn coverage(args: &[String]) -> Result<()> {
+  let arg_map = parse_arguments(args, &["--bin"])?;
+  if arg_map.contains_key("--help") {
     println!("Oops! This command hasn't been implemented yet...");
     return Ok(());
   }
+  if let Some(flag) = arg_map.keys().next() {
+    return Err(GojoError::Usage(
+      format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid option '{}'\n\tsee 'gojo coverage --help'\n",
+        flag
+      ),
+    ).into());
+  }
 
-  // Check if name was supplied in argument list
-  if !args.is_empty() && !args[0].starts_with("--") {
-    let name = &args[0];
-    Command::new(name)
-      .args(&args[1..])
-      .stdout(Stdio::inherit())
-      .stdin(Stdio::inherit())
-      .stderr(Stdio::inherit())
-      .output()?;
-    return Ok(());
+  let config = config_read().unwrap_or_else(config_default);
+
+  // Instrumented objects get a build directory of their own.
+  let coverage_dir = format!("{}/coverage", config.build_dir);
+  let clang = toolchain::is_clang();
+  let mut options = BuildOptions::new(coverage_dir.as_str());
+  options.tests = true;
+  options.quiet = config.quiet;
+  options.cxx_flags.push(coverage::compile_flags(clang));
+  options.link_flags.push(coverage::link_flags(clang));
+  build_project(&config, &options)?;
+
+  coverage::run(
+    config.project_root.as_str(),
+    coverage_dir.as_str(),
+    clang,
+    config.coverage_line_threshold,
+    config.coverage_branch_threshold,
+  )
+}
+
+pub fn pgo(args: &[String]) -> Result<()> {
+  let mut bin: Option<&str> = None;
+  let arg_map = parse_arguments(args, &["--bin"])?;
+  for (flag, val) in arg_map {
+    match flag {
+      "--bin" => {
+        bin = val;
+      }
+      "--help" => {
+        println!("Oops! This command hasn't been implemented yet...");
+        return Ok(());
+      }
+      _ => {
+        return Err(GojoError::Usage(
+          format!(
+            "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid option '{}'\n\tsee 'gojo pgo --help'\n",
+            flag
+          ),
+        ).into());
+      }
+    }
   }
 
-  // Check gojo config file for executable name
-  if !name.is_empty() {
-    if fs::exists(format!("{}/{}", build_dir.as_str(), name.as_str()))? {
-      Command::new(format!("{}/{}", build_dir, name))
-        .args(args)
-        .stdout(Stdio::inherit())
-        .stdin(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .output()?;
-      return Ok(());
+  let config = config_read().unwrap_or_else(config_default);
+
+  let clang = toolchain::is_clang();
+  let baseline_dir = format!("{}/pgo-baseline", config.build_dir);
+  let instrumented_dir = format!("{}/pgo-instrumented", config.build_dir);
+  let optimized_dir = format!("{}/pgo-optimized", config.build_dir);
+  let profile_dir = format!("{}/pgo-profiles", config.build_dir);
+  let train = config.pgo_train.as_str();
+
+  // Without a training command the test suite is the workload, so every
+  // variant builds the tests.
+  let variant = |dir: &str, flags: String| -> BuildOptions {
+    let mut options = BuildOptions::new(dir);
+    options.release = true;
+    options.lto = config.lto;
+    options.tests = train.is_empty();
+    options.quiet = config.quiet;
+    if !flags.is_empty() {
+      options.cxx_flags.push(flags.clone());
+      options.link_flags.push(flags);
+    }
+    options
+  };
+
+  // The same executable in a variant's build directory. A training command
+  // needs it, the test suite only uses it to compare sizes.
+  let binary = |dir: &str| -> Result<Option<String>> {
+    let executables = project_executables(&config, dir)?;
+    match executables::select(&executables, bin, None, config.name.as_str()) {
+      Ok(executable) => Ok(Some(executable.path.clone())),
+      Err(_) if train.is_empty() && bin.is_none() => Ok(None),
+      Err(e) => Err(e),
     }
+  };
+
+  build_project(&config, &variant(baseline_dir.as_str(), String::new()))?;
+  build_project(
+    &config,
+    &variant(instrumented_dir.as_str(), pgo::instrument_flags(clang, profile_dir.as_str(), instrumented_dir.as_str())),
+  )?;
+
+  let instrumented_bin = binary(instrumented_dir.as_str())?.unwrap_or_default();
+  if !train.is_empty() && !fs::exists(instrumented_bin.as_str())? {
+    return Err(GojoError::Failed(
+      "\x1b[31mfile not found:\x1b[0m\n\tno executable target found\n".to_string(),
+    ).into());
   }
+  pgo::train(train, instrumented_dir.as_str(), instrumented_bin.as_str(), profile_dir.as_str())?;
+  pgo::merge(clang, profile_dir.as_str())?;
 
-  return Err(Error::new(
-    ErrorKind::Other,
-    "\x1b[31mfile not found:\x1b[0m\n\tno executable target found\n",
-  ));
+  build_project(
+    &config,
+    &variant(optimized_dir.as_str(), pgo::use_flags(clang, profile_dir.as_str(), optimized_dir.as_str())),
+  )?;
+  pgo::report(
+    train,
+    baseline_dir.as_str(),
+    binary(baseline_dir.as_str())?.as_deref(),
+    optimized_dir.as_str(),
+    binary(optimized_dir.as_str())?.as_deref(),
+  )
 }
 
-pub fn test() -> Result<()> {
-  let config_result = config_read();
-  let mut build_dir = String::from(DEFAULT_BUILD_DIR);
-  if config_result.is_some() {
-    build_dir = config_result.unwrap().build_dir;
+pub fn package(args: &[String]) -> Result<()> {
+  let mut format = "tgz";
+
+  let arg_map = parse_arguments(args, &["--format", "-f"])?;
+  for (flag, val) in arg_map {
+    match flag {
+      "--format" | "-f" => {
+        format = val.unwrap_or("");
+      }
+      "--help" => {
+        println!("Oops! This command hasn't been implemented yet...");
+        return Ok(());
+      }
+      _ => {
+        return Err(GojoError::Usage(
+          format!(
+            "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid option '{}'\n\tsee 'gojo package --help'\n",
+            flag
+          ),
+        ).into());
+      }
+    }
+  }
+  let Some(generator) = package::generator(format) else {
+    return Err(GojoError::Usage(
+      format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\t--format must be one of {}\n\tsee 'gojo package --help'\n",
+        package::FORMATS.iter().map(|(name, _)| format!("'{name}'")).collect::<Vec<String>>().join(", ")
+      ),
+    ).into());
+  };
+
+  let config = config_read().unwrap_or_else(config_default);
+  require_cmake_backend(&config, "packaging is done by CPack")?;
+  // Debian refuses packages without a maintainer.
+  if format == "deb" && config.maintainer.is_empty() {
+    return Err(GojoError::Config(
+      "\x1b[31mmissing package metadata:\x1b[0m\n\tdeb packages need a maintainer, set 'maintainer' in .gojo\n".to_string(),
+    ).into());
   }
 
-  std::env::set_var("GTEST_COLOR", "1");
-  Command::new("ctest")
-    .args(["-V"])
-    .current_dir(build_dir)
-    .stdout(Stdio::inherit())
-    .output()?;
+  // Packages get a build directory of their own, so the install rules and
+  // CPack setup never leak into regular builds.
+  let package_dir = format!("{}/package", config.build_dir);
+  let metadata = package::Metadata {
+    name: config.name.as_str(),
+    version: config.version.as_str(),
+    description: config.description.as_str(),
+    maintainer: config.maintainer.as_str(),
+    license: config.license.as_str(),
+  };
+  let mut options = BuildOptions::new(package_dir.as_str());
+  options.release = true;
+  options.lto = config.lto;
+  options.quiet = config.quiet;
+  options.hook = install_hook(&config);
+  options.hook.extend(package::cpack_code(config.project_root.as_str(), &metadata));
+  build_project(&config, &options)?;
+
+  package::run(package_dir.as_str(), generator, format!("{}/dist", config.project_root).as_str())
+}
+
+pub fn deploy(args: &[String]) -> Result<()> {
+  let mut prefix = deploy::default_prefix();
+
+  let arg_map = parse_arguments(args, &["--prefix", "-p"])?;
+  for (flag, val) in arg_map {
+    match flag {
+      "--prefix" | "-p" => {
+        let Some(dir) = val else {
+          return Err(GojoError::Usage(
+            "\x1b[31mincorrect usage:\x1b[0m\n\t--prefix needs a directory\n\tsee 'gojo deploy --help'\n".to_string(),
+          ).into());
+        };
+        prefix = dir.to_string();
+      }
+      "--help" => {
+        println!("Oops! This command hasn't been implemented yet...");
+        return Ok(());
+      }
+      _ => {
+        return Err(GojoError::Usage(
+          format!(
+            "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid option '{}'\n\tsee 'gojo deploy --help'\n",
+            flag
+          ),
+        ).into());
+      }
+    }
+  }
+
+  let config = config_read().unwrap_or_else(config_default);
+  require_cmake_backend(&config, "deploying is done by 'cmake --install'")?;
+
+  // Deploys build in release mode, next to the regular builds.
+  let deploy_dir = format!("{}/deploy", config.build_dir);
+  let mut options = BuildOptions::new(deploy_dir.as_str());
+  options.release = true;
+  options.lto = config.lto;
+  options.quiet = config.quiet;
+  options.hook = install_hook(&config);
+  build_project(&config, &options)?;
+
+  deploy::install(config.project_root.as_str(), deploy_dir.as_str(), deploy::absolute(prefix.as_str())?.as_str())
+}
+
+pub fn undeploy(args: &[String]) -> Result<()> {
+  let arg_map = parse_arguments(args, &[])?;
+  if arg_map.contains_key("--help") {
+    println!("Oops! This command hasn't been implemented yet...");
+    return Ok(());
+  }
+  if let Some(flag) = arg_map.keys().next() {
+    return Err(GojoError::Usage(
+      format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid option '{}'\n\tsee 'gojo undeploy --help'\n",
+        flag
+      ),
+    ).into());
+  }
+
+  let config = config_read().unwrap_or_else(config_default);
+  deploy::uninstall(config.project_root.as_str())
+}
+
+// Install rules for the executable, unless the project has its own.
+fn install_hook(config: &GojoConfig) -> Vec<String> {
+  if package::has_install_rules(config.project_root.as_str(), config.build_dir.as_str()) {
+    return Vec::new();
+  }
+  vec![package::install_code(config.name.as_str())]
+}
 
-  println!();
+fn require_cmake_backend(config: &GojoConfig, reason: &str) -> Result<()> {
+  if config.backend == "native" {
+    return Err(GojoError::Config(
+      format!("\x1b[31munsupported backend:\x1b[0m\n\t{reason} and needs the 'cmake' backend\n"),
+    ).into());
+  }
   Ok(())
 }
 
+pub fn cache(args: &[String]) -> Result<()> {
+  // Not for users: CMake runs every compile through this as the compiler
+  // launcher, `gojo cache compile <project root> <compiler> <args>...`.
+  if args.len() > 2 && args[0] == "compile" {
+    std::process::exit(cache::launch(args[1].as_str(), &args[2..]));
+  }
+
+  // The size limit comes from the project when there is one, the cache
+  // itself is shared.
+  let config = if Path::new(CONFIG_FILE).exists() { config_read().unwrap_or_else(config_default) } else { config_default() };
+  match args.first().map(|arg| arg.as_str()) {
+    Some("stats") if args.len() == 1 => cache::stats(config.cache_max_mb as u64 * 1024 * 1024),
+    Some("clear") if args.len() == 1 => cache::clear(),
+    Some("--help") => {
+      println!("Oops! This command hasn't been implemented yet...");
+      Ok(())
+    }
+    _ => Err(GojoError::Usage(
+      "\x1b[31mincorrect usage:\x1b[0m\n\tgojo cache <stats|clear>\n\tsee 'gojo cache --help'\n".to_string(),
+    ).into()),
+  }
+}
+
 pub fn clean() -> Result<()> {
   let config_result = config_read();
   let mut build_dir = String::from(DEFAULT_BUILD_DIR);
@@ -477,18 +1578,17 @@ pub fn fmt(args: &[String]) -> Result<()> {
     style = fmt_style;
   }
 
-  let arg_map = parse_arguments(&args);
+  let arg_map = parse_arguments(&args, &["--style"])?;
   for (flag, val) in arg_map {
     match flag {
       "--style" => {
         if val.is_none() || !styles.contains(&val.unwrap()) {
-          return Err(Error::new(
-            ErrorKind::Other,
+          return Err(GojoError::Usage(
             format!(
               "\x1b[31mincorrect usage:\x1b[0m\n\tstyle not found: {}\n\tsee 'gojo fmt --help'\n",
               &val.unwrap()
             ),
-          ));
+          ).into());
         }
         style = val.unwrap();
       }
@@ -503,23 +1603,21 @@ pub fn fmt(args: &[String]) -> Result<()> {
         return Ok(());
       }
       _ => {
-        return Err(Error::new(
-          ErrorKind::Other,
+        return Err(GojoError::Usage(
           format!(
             "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid option '{}'\n\tsee 'gojo fmt --help'\n",
             args[0].as_str()
           ),
-        ));
+        ).into());
       }
     }
   }
 
   if file {
     if !fs::exists(format!("{}/.clang-format", project_root))? {
-      return Err(Error::new(
-        ErrorKind::Other,
-        "\x1b[31mfile not found:\x1b[0m\n\tno .clang-format file found\n\tsee 'gojo fmt --help'\n",
-      ));
+      return Err(GojoError::Failed(
+        "\x1b[31mfile not found:\x1b[0m\n\tno .clang-format file found\n\tsee 'gojo fmt --help'\n".to_string(),
+      ).into());
     }
     style = "file";
   }
@@ -539,7 +1637,8 @@ pub fn fmt(args: &[String]) -> Result<()> {
     cpplint_args: config.cpplint_args,
     cppcheck: config.cppcheck,
     cppcheck_args: config.cppcheck_args,
-    quiet: config.quiet
+    quiet: config.quiet,
+    ..config
   })?;
 
   let mut src_files: Vec<String> =
@@ -550,12 +1649,16 @@ pub fn fmt(args: &[String]) -> Result<()> {
   collect_src_files(PathBuf::from(format!("{}/src", project_root)), &mut src_files)?;
   collect_src_files(PathBuf::from(format!("{}/test", project_root)), &mut src_files)?;
 
-  Command::new("clang-format")
+  let status = Command::new("clang-format")
     .args(src_files.as_slice())
     .stdin(Stdio::null())
-    .stdout(Stdio::inherit())
+    .stdout(output::child_stdout())
     .stderr(Stdio::inherit())
-    .output()?;
+    .status()
+    .map_err(|e| toolchain::spawn_error("clang-format", e))?;
+  if !status.success() {
+    return Err(GojoError::Failed("\x1b[31mclang-format failed to format the project\x1b[0m\n\n".to_string()).into());
+  }
 
   Ok(())
 }
@@ -573,17 +1676,17 @@ pub fn check() -> Result<()> {
     fs::create_dir(config.build_dir.as_str())?;
   }
 
-  print!("\x1b[1;35mRunning checks...\x1b[0m\n");
+  status!("\x1b[1;35mRunning checks...\x1b[0m\n");
   let start = time::Instant::now();
 
   if config.cpplint {
-    print!("\x1b[0;35mRunning cpplint...\x1b[0m\n\n");
+    status!("\x1b[0;35mRunning cpplint...\x1b[0m\n\n");
 
     let cpplint_start = time::Instant::now();
     // TODO: Run cpplint
     let cpplint_time = cpplint_start.elapsed();
 
-    print!("\n\x1b[1;32mcpplint passed\x1b[0m ({}s)\n\n", cpplint_time.as_secs());
+    status!("\n\x1b[1;32mcpplint passed\x1b[0m ({}s)\n\n", cpplint_time.as_secs());
   }
 
   if config.cppcheck {
@@ -596,65 +1699,164 @@ pub fn check() -> Result<()> {
     collect_src_files(PathBuf::from(format!("{}/src", config.project_root.as_str())), &mut src_files)?;
     collect_src_files(PathBuf::from(format!("{}/test", config.project_root.as_str())), &mut src_files)?;
 
-    print!("\x1b[0;35mRunning cppcheck...\x1b[0m\n\n");
+    status!("\x1b[0;35mRunning cppcheck...\x1b[0m\n\n");
 
     let cppcheck_start = time::Instant::now();
-    Command::new("cppcheck")
-      .args(src_files.as_slice())
-      .stdout(Stdio::inherit())
-      .stderr(Stdio::inherit())
-      .output()?;
+    let (cppcheck_status, cppcheck_output) = output::capture(
+      Command::new("cppcheck").args(src_files.as_slice()),
+      |line| statusln!("{line}"),
+    )
+    .map_err(|e| toolchain::spawn_error("cppcheck", e))?;
 
     let cppcheck_time = cppcheck_start.elapsed();
-    print!("\n\x1b[1;32mcppcheck passed\x1b[0m ({}s)\n\n", cppcheck_time.as_secs());
+    let findings = diagnostics::parse_findings(&cppcheck_output);
+    for finding in findings.iter() {
+      finding.emit_event("cppcheck");
+    }
+    Event::new("check-finished")
+      .with("tool", "cppcheck")
+      .with("success", cppcheck_status.success())
+      .with("duration_ms", cppcheck_time.as_millis() as u64)
+      .with("findings", findings.len())
+      .emit();
+    if !cppcheck_status.success() {
+      return Err(GojoError::CheckFailed("\n\x1b[31mcppcheck failed\x1b[0m\n\n".to_string()).into());
+    }
+    status!("\n\x1b[1;32mcppcheck passed\x1b[0m ({}s)\n\n", cppcheck_time.as_secs());
   }
 
   let num_cores = num_cpus::get().to_string();
 
   if config.clang_tidy {
-    print!("\x1b[0;35mRunning clang-tidy...\x1b[0m\n\n");
-    print!("\x1b[0;35mInitliazing CMake in\x1b[0m {}\n", config.build_dir.as_str());
+    status!("\x1b[0;35mRunning clang-tidy...\x1b[0m\n\n");
     let compile_start = time::Instant::now();
-    Command::new("cmake")
-      .args([
-        "-DCMAKE_BUILD_TYPE=Release",
-        "-DBUILD_TESTING=ON",
-        "-DSTATIC_CHECK=ON",
-        "-S",
-        ".",
-        "-B",
-        config.build_dir.as_str(),
-      ])
-      //.stdout(Stdio::inherit())
-      .stdout(Stdio::null())
-      .stderr(Stdio::inherit())
-      .output()?;
-
-    print!("\x1b[1;35mCompiling\x1b[0m {} \x1b[1;35min\x1b[0m \x1b[1;36mRelease\x1b[0m \x1b[1;35mmode\x1b[0m\n\n", config.name);
-    Command::new("cmake")
-      .args(["--build", config.build_dir.as_str(), "-j", num_cores.as_str()])
-      .stdout(Stdio::null())
-      .stderr(Stdio::inherit())
-      .output()?;
+    let (tidy_status, tidy_output) = if config.backend == "native" {
+      // Without CMake to run clang-tidy as part of the build, it is run over
+      // the compilation database of a release build with the tests.
+      let mut options = BuildOptions::new(config.build_dir.as_str());
+      options.release = true;
+      options.tests = true;
+      options.quiet = true;
+      build_project(&config, &options)?;
+      let sources = clangd::database_sources(config.build_dir.as_str());
+      output::capture(
+        Command::new("clang-tidy").args(["--quiet", "-p", config.build_dir.as_str()]).args(sources.as_slice()),
+        |line| statusln!("{line}"),
+      )
+      .map_err(|e| toolchain::spawn_error("clang-tidy", e))?
+    } else {
+      let configure_flags = vec![
+        String::from("-DCMAKE_BUILD_TYPE=Release"),
+        String::from("-DBUILD_TESTING=ON"),
+        String::from("-DSTATIC_CHECK=ON"),
+        String::from("-DCMAKE_CXX_FLAGS="),
+      ];
+      configure(config.build_dir.as_str(), &configure_flags, false)?;
+
+      status!("\x1b[1;35mCompiling\x1b[0m {} \x1b[1;35min\x1b[0m \x1b[1;36mRelease\x1b[0m \x1b[1;35mmode\x1b[0m\n\n", config.name);
+      // Generator progress lines are dropped, clang-tidy's reports are kept.
+      output::capture(
+        Command::new("cmake").args(["--build", config.build_dir.as_str(), "-j", num_cores.as_str()]),
+        |line| {
+          if !line.starts_with('[') {
+            statusln!("{line}");
+          }
+        },
+      )
+      .map_err(|e| toolchain::spawn_error("cmake", e))?
+    };
 
     let compile_time = compile_start.elapsed();
-    print!("\x1b[1;32mBuild successful\x1b[0m ({}s)\n\n", compile_time.as_secs());
+    let findings = diagnostics::parse_findings(&tidy_output);
+    for finding in findings.iter() {
+      finding.emit_event("clang-tidy");
+    }
+    Event::new("check-finished")
+      .with("tool", "clang-tidy")
+      .with("success", tidy_status.success())
+      .with("duration_ms", compile_time.as_millis() as u64)
+      .with("findings", findings.len())
+      .emit();
+    // clang-tidy's errors fail the check.
+    if !tidy_status.success() {
+      return Err(GojoError::CheckFailed("\n\x1b[31mclang-tidy failed\x1b[0m\n\n".to_string()).into());
+    }
+    status!("\x1b[1;32mBuild successful\x1b[0m ({}s)\n\n", compile_time.as_secs());
     
-    print!("\x1b[1;32mclang-tidy passed\x1b[0m ({}s)\n", compile_time.as_secs());
+    status!("\x1b[1;32mclang-tidy passed\x1b[0m ({}s)\n", compile_time.as_secs());
     }
 
     let total_time = start.elapsed();
-    print!("\x1b[1;32mAll checks passed\x1b[0m ({}s)\n\n", total_time.as_secs());
+    status!("\x1b[1;32mAll checks passed\x1b[0m ({}s)\n\n", total_time.as_secs());
   Ok(())
 }
 
+#[cfg(target_os = "linux")]
+pub fn watch(args: &[String]) -> Result<()> {
+  const WATCH_COMMANDS: &[&str] = &["build", "test", "run", "check"];
+
+  let mut command = "build";
+  let mut command_args: &[String] = &[];
+  if !args.is_empty() {
+    if args[0] == "--help" {
+      println!("Oops! This command hasn't been implemented yet...");
+      return Ok(());
+    }
+    if !WATCH_COMMANDS.contains(&args[0].as_str()) {
+      return Err(GojoError::Usage(
+        format!(
+          "\x1b[31mincorrect usage:\x1b[0m\n\tcannot watch '{}', expected one of: {}\n\tsee 'gojo watch --help'\n",
+          args[0],
+          WATCH_COMMANDS.join(", ")
+        ),
+      ).into());
+    }
+    command = args[0].as_str();
+    command_args = &args[1..];
+  }
+
+  let config = config_read().unwrap_or_else(config_default);
+
+  // The sources, the CMake files and the config: everything a build reads.
+  let mut files: Vec<String> = Vec::new();
+  let mut source_dirs: Vec<PathBuf> = Vec::new();
+  for dir in ["src", "test"] {
+    let path = PathBuf::from(format!("{}/{}", config.project_root.as_str(), dir));
+    if path.exists() {
+      collect_src_files(path.clone(), &mut files)?;
+      source_dirs.push(path);
+    }
+  }
+  fingerprint::collect_cmake_files(Path::new(config.project_root.as_str()), config.build_dir.as_str(), &mut files);
+  let config_file = Path::new(config.project_root.as_str()).join(CONFIG_FILE);
+  if config_file.exists() {
+    files.push(config_file.to_string_lossy().to_string());
+  }
+  files.sort();
+  files.dedup();
+
+  let watcher = watch::Watcher::new(
+    files.into_iter().map(PathBuf::from).collect(),
+    source_dirs,
+    config.build_dir.as_str(),
+    config.cpp.as_str(),
+    config.hpp.as_str(),
+  )?;
+  watch::watch(command, command_args, config.project_root.as_str(), watcher)
+}
+
+// Watching is built on inotify.
+#[cfg(not(target_os = "linux"))]
+pub fn watch(_args: &[String]) -> Result<()> {
+  Err(GojoError::Usage(String::from("\x1b[31mincorrect usage:\x1b[0m\n\tgojo watch needs Linux\n")).into())
+}
+
 pub fn branch(args: &[String]) -> Result<()> {
-  let arg_map = parse_arguments(&args);
+  let arg_map = parse_arguments(&args, &[])?;
   if arg_map.is_empty() || arg_map.len() > 1 {
-    return Err(Error::new(
-      ErrorKind::Other,
-        "\x1b[31mincorrect usage:\x1b[0m\n\tno branch name supplied\n\tsee 'gojo fmt --help'\n"
-      )
+    return Err(GojoError::Usage(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tno branch name supplied\n\tsee 'gojo fmt --help'\n".to_string()
+      ).into()
     );
   }
 
@@ -664,42 +1866,50 @@ pub fn branch(args: &[String]) -> Result<()> {
   }
 
   for (flag, _) in arg_map {
-    Command::new("git").arg("pull").stdout(Stdio::inherit()).stderr(Stdio::inherit()).output()?;
-    Command::new("git").args(["switch", "-c", flag]).stdout(Stdio::inherit()).stderr(Stdio::inherit()).output()?;
-    Command::new("git").args(["branch", "--set-upstream-to=origin/main", flag]).stdout(Stdio::inherit()).stderr(Stdio::inherit()).output()?;
+    let steps: [&[&str]; 3] = [&["pull"], &["switch", "-c", flag], &["branch", "--set-upstream-to=origin/main", flag]];
+    for step in steps {
+      let status = Command::new("git")
+        .args(step)
+        .stdout(output::child_stdout())
+        .stderr(Stdio::inherit())
+        .status()
+        .map_err(|e| toolchain::spawn_error("git", e))?;
+      if !status.success() {
+        return Err(GojoError::Failed(format!("\x1b[31mgit {} failed\x1b[0m\n\n", step[0])).into());
+      }
+    }
   }
   Ok(())
 }
 
 pub fn install(args: &[String]) -> Result<()> {
   if args.is_empty() {
-    return Err(Error::new(
-      ErrorKind::Other,
-      "\x1b[31mincorrect usage:\x1b[0m\n\tgojo install <package> [options]\n\tsee 'gojo install --list'\n",
-    ));
+    return Err(GojoError::Usage(
+      "\x1b[31mincorrect usage:\x1b[0m\n\tgojo install <package> [options]\n\tsee 'gojo install --list'\n".to_string(),
+    ).into());
   }
 
   let package_list = ["gtest"];
   let package_descriptions = ["Google testing framework"];
-  let arg_map = parse_arguments(&args);
+  let arg_map = parse_arguments(&args, &[])?;
   for (key, _) in arg_map {
     match key {
       "gtest" | "googletest" => {
         return packages::install_gtest();
       }
       "--list" | "-l" => {
-        print!("\x1b[1;35mUsage:\x1b[0m \x1b[0;35mgojo\x1b[0m \x1b[1;35minstall\x1b[0m \x1b[0;36m<package>\x1b[0m\n\n");
-        print!("\x1b[1;35mpackages:\x1b[0m\n\n");
+        status!("\x1b[1;35mUsage:\x1b[0m \x1b[0;35mgojo\x1b[0m \x1b[1;35minstall\x1b[0m \x1b[0;36m<package>\x1b[0m\n\n");
+        status!("\x1b[1;35mpackages:\x1b[0m\n\n");
         for (idx, package) in package_list.iter().enumerate() {
-          print!("  * \x1b[1;36m{}\x1b[0m\t\t{}\n\n", package, package_descriptions[idx]);
+          status!("  * \x1b[1;36m{}\x1b[0m\t\t{}\n\n", package, package_descriptions[idx]);
+          Event::new("package-listed").with("name", *package).with("description", package_descriptions[idx]).emit();
         }
         return Ok(());
       }
       _ => {
-        return Err(Error::new(
-          ErrorKind::Other,
+        return Err(GojoError::Usage(
           format!("\x1b[31mpackage not found:\x1b[0m{}\n\tsee 'gojo install --list'\n", key)
-        ));
+        ).into());
       }
     }
   }
@@ -711,7 +1921,10 @@ pub fn help() {
 }
 
 
-fn parse_arguments(args: &[String]) -> HashMap<&str, Option<&str>> {
+// Flags in `value_flags` take the next argument as their value, whatever it
+// looks like, unless it's given as `--flag=value`. Any other flag is a
+// switch and never takes one, so `--release --tests` sets both.
+fn parse_arguments<'a>(args: &'a [String], value_flags: &[&str]) -> Result<HashMap<&'a str, Option<&'a str>>> {
   let mut parsed_args = HashMap::new();
 
   let mut iter = args.iter();
@@ -724,11 +1937,12 @@ fn parse_arguments(args: &[String]) -> HashMap<&str, Option<&str>> {
         let split: Vec<&str> = arg_str.split("=").collect();
         parsed_args.insert(split[0], Some(split[1]));
       } else {
-        let value = iter.next();
-        if value.is_some() {
-          parsed_args.insert(arg_str, Some(value.unwrap().as_str()));
-        } else {
+        if !value_flags.contains(&arg_str) {
           parsed_args.insert(arg_str, None);
+        } else if let Some(value) = iter.next() {
+          parsed_args.insert(arg_str, Some(value.as_str()));
+        } else {
+          return Err(GojoError::Usage(format!("\x1b[31mincorrect usage:\x1b[0m\n\t'{arg_str}' requires a value\n")).into());
         }
       }
     } else {
@@ -737,7 +1951,7 @@ fn parse_arguments(args: &[String]) -> HashMap<&str, Option<&str>> {
     }
     arg = iter.next();
   }
-  parsed_args
+  Ok(parsed_args)
 }
 
 fn collect_src_files(
@@ -779,7 +1993,22 @@ cpplint: {}
 cpplint_args: {}
 cppcheck: {}
 cppcheck_args: {}
-quiet: {}",
+quiet: {}
+coverage_line_threshold: {}
+coverage_branch_threshold: {}
+lto: {}
+pgo_train: {}
+backend: {}
+unity_batch: {}
+version: {}
+description: {}
+maintainer: {}
+license: {}
+cache: {}
+cache_max_mb: {}
+debugger: {}
+backtrace: {}{}{}{}
+",
       config.project_root,
       config.build_dir,
       config.name,
@@ -793,7 +2022,24 @@ quiet: {}",
       config.cpplint_args,
       config.cppcheck,
       config.cppcheck_args,
-      config.quiet
+      config.quiet,
+      config.coverage_line_threshold,
+      config.coverage_branch_threshold,
+      config.lto,
+      config.pgo_train,
+      config.backend,
+      config.unity_batch,
+      config.version,
+      config.description,
+      config.maintainer,
+      config.license,
+      config.cache,
+      config.cache_max_mb,
+      config.debugger,
+      config.backtrace,
+      config.targets.iter().map(|target| format!("\ntarget: {target}")).collect::<String>(),
+      config.pch.iter().map(|pch| format!("\npch: {pch}")).collect::<String>(),
+      config.runs.iter().map(|(key, value)| format!("\nrun.{key}: {value}")).collect::<String>()
     )
   )?;
   Ok(())
@@ -806,70 +2052,78 @@ fn config_read() -> Option<GojoConfig> {
     return None;
   }
 
+  // Keys missing from older config files fall back to their defaults.
   let file_contents = config_result.unwrap();
-  let parsed_config: Vec<&str> = file_contents.split("\n").collect();
-  let parsed_project_root: Vec<&str> = parsed_config[0].split(":").collect();
-  let parsed_build_dir: Vec<&str> = parsed_config[1].split(":").collect();
-  let parsed_name: Vec<&str> = parsed_config[2].split(":").collect();
-  let parsed_std: Vec<&str> = parsed_config[3].split(":").collect();
-  let parsed_cpp: Vec<&str> = parsed_config[4].split(":").collect();
-  let parsed_hpp: Vec<&str> = parsed_config[5].split(":").collect();
-  let parsed_fmt_style: Vec<&str> = parsed_config[6].split(":").collect();
-  let parsed_fmt_args: Vec<&str> = parsed_config[7].split(":").collect();
-  let parsed_clang_tidy: Vec<&str> = parsed_config[8].split(":").collect();
-  let clang_tidy: bool = match parsed_clang_tidy[1].trim() {
-    "true" => {
-      true
-    }
-    _ => {
-      false
-    }
-  };
-  let parsed_cpplint: Vec<&str> = parsed_config[9].split(":").collect();
-  let cpplint: bool = match parsed_cpplint[1].trim() {
-    "true" => {
-      true
+  let mut values: HashMap<&str, &str> = HashMap::new();
+  let mut targets: Vec<String> = Vec::new();
+  let mut pch: Vec<String> = Vec::new();
+  let mut runs: Vec<(String, String)> = Vec::new();
+  for line in file_contents.split("\n") {
+    if let Some((key, value)) = line.split_once(":") {
+      if key.trim() == "target" {
+        targets.push(value.trim().to_string());
+      } else if key.trim() == "pch" {
+        pch.push(value.trim().to_string());
+      } else if let Some(run) = key.trim().strip_prefix("run.") {
+        runs.push((run.to_string(), value.trim().to_string()));
+      } else {
+        values.insert(key.trim(), value.trim());
+      }
     }
-    _ => {
-      false
+  }
+
+  let defaults = config_default();
+  let string = |key: &str, default: String| -> String {
+    match values.get(key) {
+      Some(value) => value.to_string(),
+      None => default,
     }
   };
-  let parsed_cpplint_args: Vec<&str> = parsed_config[10].split(":").collect();
-  let parsed_cppcheck: Vec<&str> = parsed_config[11].split(":").collect();
-  let cppcheck: bool = match parsed_cppcheck[1].trim() {
-    "true" => {
-      true
-    }
-    _ => {
-      false
+  let boolean = |key: &str, default: bool| -> bool {
+    match values.get(key) {
+      Some(value) => *value == "true",
+      None => default,
     }
   };
-  let parsed_cppcheck_args: Vec<&str> = parsed_config[12].split(":").collect();
-  let parsed_quiet: Vec<&str> = parsed_config[13].split(":").collect();
-  let quiet: bool = match parsed_quiet[1].trim() {
-    "true" => {
-      true
-    }
-    _ => {
-      false
+  let number = |key: &str, default: f64| -> f64 {
+    match values.get(key) {
+      Some(value) => value.parse().unwrap_or(default),
+      None => default,
     }
   };
 
-  Some(GojoConfig { 
-    project_root: String::from(parsed_project_root[1].trim()),
-    build_dir: String::from(parsed_build_dir[1].trim()),
-    name: String::from(parsed_name[1].trim()),
-    std: String::from(parsed_std[1].trim()),
-    cpp: String::from(parsed_cpp[1].trim()),
-    hpp: String::from(parsed_hpp[1].trim()),
-    fmt_style: String::from(parsed_fmt_style[1].trim()),
-    fmt_args: String::from(parsed_fmt_args[1].trim()),
-    clang_tidy: clang_tidy,
-    cpplint: cpplint,
-    cpplint_args: String::from(parsed_cpplint_args[1].trim()),
-    cppcheck: cppcheck,
-    cppcheck_args: String::from(parsed_cppcheck_args[1].trim()),
-    quiet: quiet
+  Some(GojoConfig {
+    project_root: string("project_root", defaults.project_root),
+    build_dir: string("build_dir", defaults.build_dir),
+    name: string("name", defaults.name),
+    std: string("std", defaults.std),
+    cpp: string("src", defaults.cpp),
+    hpp: string("hdr", defaults.hpp),
+    fmt_style: string("fmt_style", defaults.fmt_style),
+    fmt_args: string("fmt_args", defaults.fmt_args),
+    clang_tidy: boolean("clang-tidy", defaults.clang_tidy),
+    cpplint: boolean("cpplint", defaults.cpplint),
+    cpplint_args: string("cpplint_args", defaults.cpplint_args),
+    cppcheck: boolean("cppcheck", defaults.cppcheck),
+    cppcheck_args: string("cppcheck_args", defaults.cppcheck_args),
+    quiet: boolean("quiet", defaults.quiet),
+    coverage_line_threshold: number("coverage_line_threshold", defaults.coverage_line_threshold),
+    coverage_branch_threshold: number("coverage_branch_threshold", defaults.coverage_branch_threshold),
+    lto: boolean("lto", defaults.lto),
+    pgo_train: string("pgo_train", defaults.pgo_train),
+    backend: string("backend", defaults.backend),
+    targets,
+    pch,
+    runs,
+    unity_batch: number("unity_batch", defaults.unity_batch as f64) as usize,
+    version: string("version", defaults.version),
+    description: string("description", defaults.description),
+    maintainer: string("maintainer", defaults.maintainer),
+    license: string("license", defaults.license),
+    cache: boolean("cache", defaults.cache),
+    cache_max_mb: number("cache_max_mb", defaults.cache_max_mb as f64) as usize,
+    debugger: string("debugger", defaults.debugger),
+    backtrace: string("backtrace", defaults.backtrace),
   })
 }
 
@@ -891,7 +2145,24 @@ fn config_default() -> GojoConfig {
     cpplint_args: String::new(),
     cppcheck: false,
     cppcheck_args: String::new(),
-    quiet: false
+    quiet: false,
+    coverage_line_threshold: 0.0,
+    coverage_branch_threshold: 0.0,
+    lto: false,
+    pgo_train: String::new(),
+    backend: String::from("cmake"),
+    targets: Vec::new(),
+    pch: Vec::new(),
+    runs: Vec::new(),
+    unity_batch: 0,
+    version: String::from("1.0.0"),
+    description: String::new(),
+    maintainer: String::new(),
+    license: String::new(),
+    cache: true,
+    cache_max_mb: 5120,
+    debugger: String::new(),
+    backtrace: String::from("off"),
   }
 }
 
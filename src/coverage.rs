@@ -0,0 +1,584 @@
+use crate::output;
+use crate::output::Event;
+use crate::toolchain;
+use crate::error::GojoError;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::io::Result;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::vec::Vec;
+
+// Hit counts for one source file. Branches are kept per line in the order
+// the compiler reports them, `None` meaning the branch was never reached.
+#[derive(Default)]
+struct FileCoverage {
+  lines: BTreeMap<u32, u64>,
+  branches: BTreeMap<u32, Vec<Option<u64>>>,
+}
+
+impl FileCoverage {
+  // Headers are compiled into many translation units, so their counts are
+  // summed over every unit that includes them.
+  fn merge(&mut self, other: FileCoverage) {
+    for (line, count) in other.lines {
+      *self.lines.entry(line).or_insert(0) += count;
+    }
+    for (line, branches) in other.branches {
+      let existing = self.branches.entry(line).or_default();
+      for (i, taken) in branches.into_iter().enumerate() {
+        if i >= existing.len() {
+          existing.push(taken);
+        } else if let Some(taken) = taken {
+          existing[i] = Some(existing[i].unwrap_or(0) + taken);
+        }
+      }
+    }
+  }
+}
+
+#[derive(Default)]
+struct Totals {
+  lines_hit: usize,
+  lines_total: usize,
+  branches_hit: usize,
+  branches_total: usize,
+}
+
+impl Totals {
+  fn of(file: &FileCoverage) -> Totals {
+    let mut totals = Totals::default();
+    totals.add(file);
+    totals
+  }
+
+  fn add(&mut self, file: &FileCoverage) {
+    self.lines_total += file.lines.len();
+    self.lines_hit += file.lines.values().filter(|count| **count > 0).count();
+    for branches in file.branches.values() {
+      self.branches_total += branches.len();
+      self.branches_hit += branches.iter().filter(|taken| matches!(taken, Some(n) if *n > 0)).count();
+    }
+  }
+
+  fn line_percent(&self) -> Option<f64> {
+    percent(self.lines_hit, self.lines_total)
+  }
+
+  fn branch_percent(&self) -> Option<f64> {
+    percent(self.branches_hit, self.branches_total)
+  }
+}
+
+struct Report {
+  project_root: String,
+  // The project's own sources under src/.
+  project: BTreeMap<String, FileCoverage>,
+  // Headers installed under ~/.gojo.
+  dependencies: BTreeMap<String, FileCoverage>,
+}
+
+pub fn compile_flags(clang: bool) -> String {
+  if clang {
+    return String::from("-fprofile-instr-generate -fcoverage-mapping");
+  }
+  String::from("--coverage")
+}
+
+pub fn link_flags(clang: bool) -> String {
+  if clang {
+    return String::from("-fprofile-instr-generate");
+  }
+  String::from("--coverage")
+}
+
+// Runs the test suite in the instrumented `coverage_dir`, then writes an lcov
+// tracefile and an HTML report to `coverage_dir/report`. Fails when a test
+// fails or project coverage is below a non-zero threshold.
+pub fn run(project_root: &str, coverage_dir: &str, clang: bool, line_threshold: f64, branch_threshold: f64) -> Result<()> {
+  // Counters from a previous run would be added to this one's.
+  let profile_dir = format!("{coverage_dir}/profiles");
+  let _ = fs::remove_dir_all(profile_dir.as_str());
+  for data_file in find_files(Path::new(coverage_dir), ".gcda") {
+    let _ = fs::remove_file(data_file);
+  }
+
+  status!("\x1b[1;35mRunning tests\x1b[0m with coverage\n\n");
+  let tests = Command::new("ctest")
+    .arg("--output-on-failure")
+    .current_dir(coverage_dir)
+    .env("LLVM_PROFILE_FILE", format!("{profile_dir}/%p-%m.profraw"))
+    .env("GTEST_COLOR", "1")
+    .stdout(output::child_stdout())
+    .stderr(Stdio::inherit())
+    .status()
+    .map_err(|e| toolchain::spawn_error("ctest", e))?;
+
+  let files = if clang {
+    collect_llvm(coverage_dir)?
+  } else {
+    collect_gcov(coverage_dir)?
+  };
+  let report = classify(project_root, files);
+  if report.project.is_empty() && report.dependencies.is_empty() {
+    return Err(GojoError::Failed(
+      "\x1b[31mno coverage data found:\x1b[0m\n\tno tests ran any code under src/\n".to_string(),
+    ).into());
+  }
+
+  let report_dir = format!("{coverage_dir}/report");
+  fs::create_dir_all(report_dir.as_str())?;
+  let lcov_path = format!("{report_dir}/lcov.info");
+  let html_path = format!("{report_dir}/index.html");
+  fs::write(lcov_path.as_str(), to_lcov(&report.project))?;
+  fs::write(html_path.as_str(), to_html(&report))?;
+
+  let mut totals = Totals::default();
+  for file in report.project.values() {
+    totals.add(file);
+  }
+  print_summary(&report, &totals);
+  status!("\x1b[1;35mCoverage written to\x1b[0m {}\n\n", html_path);
+  Event::new("coverage-report")
+    .with("files", report.project.len())
+    .with("lines_hit", totals.lines_hit)
+    .with("lines_total", totals.lines_total)
+    .with("line_percent", totals.line_percent())
+    .with("branches_hit", totals.branches_hit)
+    .with("branches_total", totals.branches_total)
+    .with("branch_percent", totals.branch_percent())
+    .with("lcov", lcov_path)
+    .with("html", html_path)
+    .emit();
+
+  if !tests.success() {
+    return Err(GojoError::TestFailed(
+      "\x1b[31mtests failed:\x1b[0m\n\tcoverage was collected from a failing test suite\n".to_string(),
+    ).into());
+  }
+  for (kind, actual, threshold) in [
+    ("line", totals.line_percent(), line_threshold),
+    ("branch", totals.branch_percent(), branch_threshold),
+  ] {
+    if let Some(actual) = actual {
+      if threshold > 0.0 && actual < threshold {
+        return Err(GojoError::TestFailed(
+          format!(
+            "\x1b[31mcoverage below threshold:\x1b[0m\n\t{kind} coverage is {actual:.1}%, the configured minimum is {threshold}%\n"
+          ),
+        ).into());
+      }
+    }
+  }
+  Ok(())
+}
+
+// GCC writes a .gcda file next to each object. gcov turns each into one
+// .gcov listing per source file it touched, written to the working directory.
+fn collect_gcov(coverage_dir: &str) -> Result<BTreeMap<String, FileCoverage>> {
+  let work_dir = format!("{coverage_dir}/gcov");
+  let _ = fs::remove_dir_all(work_dir.as_str());
+  fs::create_dir_all(work_dir.as_str())?;
+
+  let mut files: BTreeMap<String, FileCoverage> = BTreeMap::new();
+  for data_file in find_files(Path::new(coverage_dir), ".gcda") {
+    let result = Command::new("gcov")
+      .args(["-b", "-c", "-p"])
+      .arg(data_file)
+      .current_dir(work_dir.as_str())
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .status();
+    if let Err(e) = result {
+      return Err(toolchain::spawn_error("gcov", e));
+    }
+
+    for entry in fs::read_dir(work_dir.as_str())?.flatten() {
+      if let Ok(contents) = fs::read_to_string(entry.path()) {
+        if let Some((source, coverage)) = parse_gcov(contents.as_str()) {
+          files.entry(source).or_default().merge(coverage);
+        }
+      }
+      let _ = fs::remove_file(entry.path());
+    }
+  }
+  let _ = fs::remove_dir_all(work_dir.as_str());
+  Ok(files)
+}
+
+fn parse_gcov(contents: &str) -> Option<(String, FileCoverage)> {
+  let mut source: Option<String> = None;
+  let mut coverage = FileCoverage::default();
+  let mut current_line = 0;
+  let mut in_instantiation = false;
+  let mut lines = contents.lines().peekable();
+  while let Some(line) = lines.next() {
+    // Templates are followed by a breakdown per instantiation, whose counts
+    // are already included in the combined line above them.
+    if line.starts_with("------------------") {
+      in_instantiation = lines.peek().is_some_and(|next| !next.starts_with(' ') && next.ends_with(':'));
+      continue;
+    }
+    if in_instantiation {
+      continue;
+    }
+
+    if let Some(rest) = line.strip_prefix("branch") {
+      if current_line == 0 {
+        continue;
+      }
+      let taken = if rest.contains("never executed") {
+        None
+      } else {
+        Some(rest.split_whitespace().nth(2).and_then(|n| n.parse().ok()).unwrap_or(0))
+      };
+      coverage.branches.entry(current_line).or_default().push(taken);
+      continue;
+    }
+
+    let fields: Vec<&str> = line.splitn(3, ':').collect();
+    if fields.len() < 3 {
+      continue;
+    }
+    let Ok(line_number) = fields[1].trim().parse::<u32>() else {
+      continue;
+    };
+    if line_number == 0 {
+      if let Some(path) = fields[2].strip_prefix("Source:") {
+        source = Some(fs::canonicalize(path).map(|p| p.to_string_lossy().to_string()).unwrap_or(path.to_string()));
+      }
+      continue;
+    }
+    current_line = line_number;
+    let count = match fields[0].trim() {
+      "-" => continue,
+      "#####" | "=====" => 0,
+      count => count.trim_end_matches('*').parse().unwrap_or(0),
+    };
+    *coverage.lines.entry(line_number).or_insert(0) += count;
+  }
+  Some((source?, coverage))
+}
+
+// Clang writes raw profiles while the tests run. They are merged, then
+// llvm-cov maps them back onto every instrumented binary as lcov.
+fn collect_llvm(coverage_dir: &str) -> Result<BTreeMap<String, FileCoverage>> {
+  let profiles = find_files(Path::new(format!("{coverage_dir}/profiles").as_str()), ".profraw");
+  if profiles.is_empty() {
+    return Ok(BTreeMap::new());
+  }
+
+  let profdata = format!("{coverage_dir}/coverage.profdata");
+  let merged = Command::new("llvm-profdata")
+    .args(["merge", "-sparse", "-o", profdata.as_str()])
+    .args(profiles.iter())
+    .stdout(Stdio::null())
+    .status()
+    .map_err(|e| toolchain::spawn_error("llvm-profdata", e))?;
+  if !merged.success() {
+    return Err(GojoError::Failed(
+      "\x1b[31mllvm-profdata failed:\x1b[0m\n\tcould not merge the raw coverage profiles\n".to_string(),
+    ).into());
+  }
+
+  let binaries = find_binaries(Path::new(coverage_dir));
+  if binaries.is_empty() {
+    return Ok(BTreeMap::new());
+  }
+  let mut export = Command::new("llvm-cov");
+  export
+    .args(["export", "-format=lcov"])
+    .arg(format!("-instr-profile={profdata}"))
+    .arg(binaries[0].as_path());
+  for binary in binaries.iter().skip(1) {
+    export.arg("-object").arg(binary.as_path());
+  }
+  let lcov = export.stderr(Stdio::inherit()).output().map_err(|e| toolchain::spawn_error("llvm-cov", e))?;
+  Ok(parse_lcov(String::from_utf8_lossy(&lcov.stdout).as_ref()))
+}
+
+fn parse_lcov(contents: &str) -> BTreeMap<String, FileCoverage> {
+  let mut files: BTreeMap<String, FileCoverage> = BTreeMap::new();
+  let mut source: Option<String> = None;
+  let mut coverage = FileCoverage::default();
+  for line in contents.lines() {
+    if let Some(path) = line.strip_prefix("SF:") {
+      source = Some(path.to_string());
+      coverage = FileCoverage::default();
+    } else if let Some(record) = line.strip_prefix("DA:") {
+      let fields: Vec<&str> = record.split(',').collect();
+      if fields.len() < 2 {
+        continue;
+      }
+      if let (Ok(line), Ok(count)) = (fields[0].parse::<u32>(), fields[1].parse::<u64>()) {
+        *coverage.lines.entry(line).or_insert(0) += count;
+      }
+    } else if let Some(record) = line.strip_prefix("BRDA:") {
+      let fields: Vec<&str> = record.split(',').collect();
+      if fields.len() == 4 {
+        if let Ok(line) = fields[0].parse() {
+          coverage.branches.entry(line).or_default().push(fields[3].parse().ok());
+        }
+      }
+    } else if line == "end_of_record" {
+      if let Some(path) = source.take() {
+        files.entry(path).or_default().merge(std::mem::take(&mut coverage));
+      }
+    }
+  }
+  files
+}
+
+// Keeps the project's sources and installed dependencies apart and drops
+// everything else: tests, system headers and generated files.
+fn classify(project_root: &str, files: BTreeMap<String, FileCoverage>) -> Report {
+  let src_dir = format!("{}/src/", project_root.trim_end_matches('/'));
+  let deps_dir = env::home_dir().map(|home| format!("{}/.gojo/", home.to_string_lossy()));
+
+  let mut report = Report {
+    project_root: project_root.to_string(),
+    project: BTreeMap::new(),
+    dependencies: BTreeMap::new(),
+  };
+  for (path, coverage) in files {
+    if path.starts_with(src_dir.as_str()) {
+      report.project.insert(path, coverage);
+    } else if deps_dir.as_ref().is_some_and(|dir| path.starts_with(dir.as_str())) {
+      report.dependencies.insert(path, coverage);
+    }
+  }
+  report
+}
+
+fn print_summary(report: &Report, totals: &Totals) {
+  status!("\n\x1b[1;35mCoverage\x1b[0m\n");
+  status!("  {:>8}  {:>8}  {}\n", "lines", "branches", "file");
+  for (path, coverage) in report.project.iter() {
+    let file_totals = Totals::of(coverage);
+    status!(
+      "  {}  {}  {}\n",
+      colored_percent(file_totals.line_percent()),
+      colored_percent(file_totals.branch_percent()),
+      relative(report.project_root.as_str(), path)
+    );
+  }
+  status!(
+    "  {}  {}  \x1b[1mtotal\x1b[0m ({}/{} lines, {}/{} branches)\n",
+    colored_percent(totals.line_percent()),
+    colored_percent(totals.branch_percent()),
+    totals.lines_hit,
+    totals.lines_total,
+    totals.branches_hit,
+    totals.branches_total
+  );
+
+  if !report.dependencies.is_empty() {
+    let mut dependency_totals = Totals::default();
+    for coverage in report.dependencies.values() {
+      dependency_totals.add(coverage);
+    }
+    status!(
+      "\n  {}  {}  dependencies in ~/.gojo ({} files, not counted above)\n",
+      colored_percent(dependency_totals.line_percent()),
+      colored_percent(dependency_totals.branch_percent()),
+      report.dependencies.len()
+    );
+  }
+  status!("\n");
+}
+
+fn colored_percent(value: Option<f64>) -> String {
+  match value {
+    Some(value) if value >= 80.0 => format!("\x1b[32m{:>7.1}%\x1b[0m", value),
+    Some(value) if value >= 50.0 => format!("\x1b[33m{:>7.1}%\x1b[0m", value),
+    Some(value) => format!("\x1b[31m{:>7.1}%\x1b[0m", value),
+    None => format!("{:>8}", "-"),
+  }
+}
+
+fn to_lcov(files: &BTreeMap<String, FileCoverage>) -> String {
+  let mut lcov = String::new();
+  for (path, coverage) in files.iter() {
+    lcov += format!("TN:\nSF:{path}\n").as_str();
+    for (line, branches) in coverage.branches.iter() {
+      for (i, taken) in branches.iter().enumerate() {
+        let taken = taken.map(|n| n.to_string()).unwrap_or(String::from("-"));
+        lcov += format!("BRDA:{line},0,{i},{taken}\n").as_str();
+      }
+    }
+    for (line, count) in coverage.lines.iter() {
+      lcov += format!("DA:{line},{count}\n").as_str();
+    }
+    let totals = Totals::of(coverage);
+    lcov += format!(
+      "BRF:{}\nBRH:{}\nLF:{}\nLH:{}\nend_of_record\n",
+      totals.branches_total, totals.branches_hit, totals.lines_total, totals.lines_hit
+    )
+    .as_str();
+  }
+  lcov
+}
+
+fn to_html(report: &Report) -> String {
+  let mut html = String::from(
+    "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>gojo coverage</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+td, th { text-align: left; padding: 2px 12px; font-size: 13px; }
+td.pct { text-align: right; }
+pre { font-size: 12px; line-height: 1.4; margin: 0 0 2em 0; }
+pre span { display: block; white-space: pre; }
+.hit { background: #dcf5dc; }
+.miss { background: #f8d7d7; }
+.partial { background: #fbefc9; }
+.count { color: #888; display: inline-block; width: 6em; text-align: right; margin-right: 1em; }
+</style>
+</head>
+<body>
+<h1>Coverage</h1>
+",
+  );
+
+  let mut totals = Totals::default();
+  for coverage in report.project.values() {
+    totals.add(coverage);
+  }
+  html += "<table>\n<tr><th>File</th><th>Lines</th><th>Branches</th></tr>\n";
+  for (path, coverage) in report.project.iter() {
+    let name = relative(report.project_root.as_str(), path);
+    html += html_row(format!("<a href=\"#{0}\">{0}</a>", escape_html(name.as_str())).as_str(), &Totals::of(coverage)).as_str();
+  }
+  html += html_row("<b>Total</b>", &totals).as_str();
+  html += "</table>\n";
+
+  if !report.dependencies.is_empty() {
+    html += "<h2>Dependencies</h2>\n<table>\n<tr><th>File</th><th>Lines</th><th>Branches</th></tr>\n";
+    for (path, coverage) in report.dependencies.iter() {
+      html += html_row(escape_html(path).as_str(), &Totals::of(coverage)).as_str();
+    }
+    html += "</table>\n";
+  }
+
+  for (path, coverage) in report.project.iter() {
+    let name = relative(report.project_root.as_str(), path);
+    html += format!("<h2 id=\"{0}\">{0}</h2>\n<pre>", escape_html(name.as_str())).as_str();
+    let source = fs::read_to_string(path).unwrap_or_default();
+    for (i, text) in source.lines().enumerate() {
+      let line = i as u32 + 1;
+      let branches = coverage.branches.get(&line);
+      let partial = branches.is_some_and(|b| b.iter().any(|taken| !matches!(taken, Some(n) if *n > 0)));
+      let (class, count) = match coverage.lines.get(&line) {
+        Some(0) => ("miss", String::from("0")),
+        Some(count) if partial => ("partial", count.to_string()),
+        Some(count) => ("hit", count.to_string()),
+        None => ("", String::new()),
+      };
+      html += format!(
+        "<span class=\"{}\"><span class=\"count\">{}</span>{:>5}  {}</span>",
+        class,
+        count,
+        line,
+        escape_html(text)
+      )
+      .as_str();
+    }
+    html += "</pre>\n";
+  }
+
+  html += "</body>\n</html>\n";
+  html
+}
+
+fn html_row(label: &str, totals: &Totals) -> String {
+  let cell = |value: Option<f64>| value.map(|v| format!("{v:.1}%")).unwrap_or(String::from("-"));
+  format!(
+    "<tr><td>{}</td><td class=\"pct\">{} ({}/{})</td><td class=\"pct\">{} ({}/{})</td></tr>\n",
+    label,
+    cell(totals.line_percent()),
+    totals.lines_hit,
+    totals.lines_total,
+    cell(totals.branch_percent()),
+    totals.branches_hit,
+    totals.branches_total
+  )
+}
+
+fn escape_html(text: &str) -> String {
+  text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn percent(hit: usize, total: usize) -> Option<f64> {
+  if total == 0 {
+    return None;
+  }
+  Some(hit as f64 * 100.0 / total as f64)
+}
+
+fn relative(project_root: &str, path: &str) -> String {
+  path.strip_prefix(project_root).unwrap_or(path).trim_start_matches('/').to_string()
+}
+
+fn find_files(dir: &Path, extension: &str) -> Vec<PathBuf> {
+  let mut files: Vec<PathBuf> = Vec::new();
+  let Ok(entries) = fs::read_dir(dir) else {
+    return files;
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if path.is_dir() {
+      files.extend(find_files(path.as_path(), extension));
+    } else if entry.file_name().to_string_lossy().ends_with(extension) {
+      files.push(path);
+    }
+  }
+  files
+}
+
+// Executables and shared libraries built into the coverage directory.
+fn find_binaries(dir: &Path) -> Vec<PathBuf> {
+  let mut binaries: Vec<PathBuf> = Vec::new();
+  let Ok(entries) = fs::read_dir(dir) else {
+    return binaries;
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    let name = entry.file_name().to_string_lossy().to_string();
+    if path.is_dir() {
+      if name != "CMakeFiles" {
+        binaries.extend(find_binaries(path.as_path()));
+      }
+      continue;
+    }
+    #[cfg(unix)]
+    let executable = entry.metadata().map(|m| m.permissions().mode() & 0o111 != 0).unwrap_or(false);
+    #[cfg(not(unix))]
+    let executable = name.ends_with(".exe");
+    if (executable || name.ends_with(".so") || name.ends_with(".dylib")) && is_object_file(path.as_path()) {
+      binaries.push(path);
+    }
+  }
+  binaries
+}
+
+fn is_object_file(path: &Path) -> bool {
+  let mut magic = [0u8; 4];
+  let Ok(mut file) = fs::File::open(path) else {
+    return false;
+  };
+  if file.read_exact(&mut magic).is_err() {
+    return false;
+  }
+  magic == *b"\x7fELF" || magic == [0xcf, 0xfa, 0xed, 0xfe]
+}
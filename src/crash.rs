@@ -0,0 +1,254 @@
+use crate::error::GojoError;
+use crate::output::Event;
+use crate::toolchain;
+
+use std::fs;
+use std::io::Result;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::vec::Vec;
+
+// How to get a backtrace when a program gojo started dies from a signal,
+// the `backtrace` config key.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+  Off,
+  // Run the same command again under gdb.
+  Rerun,
+  // Let the program dump core and read the core file with gdb.
+  Core,
+}
+
+pub fn mode(configured: &str) -> Result<Mode> {
+  match configured {
+    "" | "off" => Ok(Mode::Off),
+    "rerun" => Ok(Mode::Rerun),
+    "core" => Ok(Mode::Core),
+    _ => Err(
+      GojoError::Config(format!(
+        "\x1b[31minvalid config:\x1b[0m\n\t'backtrace' must be one of off, rerun or core, not '{configured}'\n"
+      ))
+      .into(),
+    ),
+  }
+}
+
+// Lifts the core file size limit for `command` and everything it starts.
+#[cfg(unix)]
+pub fn allow_core_dumps(command: &mut Command) {
+  unsafe {
+    command.pre_exec(|| {
+      let limit = libc::rlimit { rlim_cur: libc::RLIM_INFINITY, rlim_max: libc::RLIM_INFINITY };
+      // Raising the hard limit needs privileges, the soft one can go up to it.
+      if libc::setrlimit(libc::RLIMIT_CORE, &limit) != 0 {
+        let mut current = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        libc::getrlimit(libc::RLIMIT_CORE, &mut current);
+        current.rlim_cur = current.rlim_max;
+        libc::setrlimit(libc::RLIMIT_CORE, &current);
+      }
+      Ok(())
+    });
+  }
+}
+
+// Core dumps are a Unix affair.
+#[cfg(not(unix))]
+pub fn allow_core_dumps(_command: &mut Command) {}
+
+#[cfg(unix)]
+pub fn signal_name(signal: i32) -> String {
+  let name = match signal {
+    libc::SIGSEGV => "SIGSEGV",
+    libc::SIGABRT => "SIGABRT",
+    libc::SIGBUS => "SIGBUS",
+    libc::SIGFPE => "SIGFPE",
+    libc::SIGILL => "SIGILL",
+    libc::SIGTRAP => "SIGTRAP",
+    libc::SIGKILL => "SIGKILL",
+    libc::SIGTERM => "SIGTERM",
+    libc::SIGINT => "SIGINT",
+    libc::SIGPIPE => "SIGPIPE",
+    _ => return format!("signal {signal}"),
+  };
+  name.to_string()
+}
+
+#[cfg(not(unix))]
+pub fn signal_name(signal: i32) -> String {
+  format!("signal {signal}")
+}
+
+// A program that died from a signal.
+pub struct Crash<'a> {
+  // The executable or test, as the user knows it.
+  pub name: &'a str,
+  pub program: &'a str,
+  pub args: &'a [String],
+  pub cwd: &'a str,
+  // Set on top of gojo's own environment, from `.env` and run configurations.
+  pub env: &'a [(String, String)],
+  // A file the program read its input from.
+  pub stdin: Option<&'a str>,
+  // Unknown when the crash was reported by ctest.
+  pub signal: Option<i32>,
+  pub pid: Option<u32>,
+  // When the program was started, core files older than this are stale.
+  pub started: SystemTime,
+}
+
+// Prints what crashed and how to run it again, with the backtrace of the
+// crashing thread when `mode` asks for one.
+pub fn report(crash: &Crash, mode: Mode) {
+  let how = match crash.signal {
+    Some(signal) => format!("was killed by {}", signal_name(signal)),
+    None => String::from("crashed"),
+  };
+  let reproduce = reproduce_command(crash);
+  eprintln!("\n\x1b[1;31m{}\x1b[0m {}", crash.name, how);
+
+  let backtrace = match mode {
+    Mode::Off => {
+      eprintln!("\tset 'backtrace: rerun' or 'backtrace: core' in .gojo to get a backtrace");
+      Vec::new()
+    }
+    Mode::Rerun => rerun(crash),
+    Mode::Core => from_core(crash),
+  };
+  if !backtrace.is_empty() {
+    eprintln!("\x1b[1;35mBacktrace of the crashing thread:\x1b[0m");
+    for frame in backtrace.iter() {
+      eprintln!("  {frame}");
+    }
+  }
+  eprintln!("\x1b[1;35mReproduce with:\x1b[0m\n  {reproduce}\n");
+
+  Event::new("crash")
+    .with("name", crash.name)
+    .with("program", crash.program)
+    .with("signal", crash.signal.map(signal_name))
+    .with("backtrace", backtrace)
+    .with("command", reproduce.as_str())
+    .emit();
+}
+
+fn reproduce_command(crash: &Crash) -> String {
+  let mut words: Vec<String> = crash.env.iter().map(|(var, value)| format!("{var}={}", quote(value))).collect();
+  words.push(quote(crash.program));
+  words.extend(crash.args.iter().map(|arg| quote(arg)));
+  if let Some(stdin) = crash.stdin {
+    words.push(format!("< {}", quote(stdin)));
+  }
+  format!("cd {} && {}", quote(crash.cwd), words.join(" "))
+}
+
+fn quote(word: &str) -> String {
+  if !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c)) {
+    return word.to_string();
+  }
+  format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+// Marks where gdb's backtrace starts, past whatever the program printed.
+const MARKER: &str = "gojo-backtrace";
+
+fn rerun(crash: &Crash) -> Vec<String> {
+  eprintln!("\trunning it again under gdb...");
+  // gdb's `run` redirects the program's input through the shell.
+  let run = match crash.stdin {
+    Some(stdin) => format!("run < {}", quote(stdin)),
+    None => String::from("run"),
+  };
+  let mut gdb = Command::new("gdb");
+  gdb
+    .args(["-batch", "-nx", "-ex", "set pagination off", "-ex", run.as_str(), "-ex"])
+    .arg(format!("echo {MARKER}\\n"))
+    .args(["-ex", "bt", "--args", crash.program])
+    .args(crash.args)
+    .envs(crash.env.iter().map(|(var, value)| (var, value)))
+    .current_dir(crash.cwd)
+    .stdin(Stdio::null());
+  let frames = run_gdb(&mut gdb);
+  if frames.is_empty() {
+    eprintln!("\tit did not crash again under gdb");
+  }
+  frames
+}
+
+fn from_core(crash: &Crash) -> Vec<String> {
+  let Some(core) = find_core(crash) else {
+    eprintln!("\tno core file found, check 'ulimit -c' and /proc/sys/kernel/core_pattern");
+    return Vec::new();
+  };
+  eprintln!("\treading {}", core.to_string_lossy());
+  let mut gdb = Command::new("gdb");
+  gdb
+    .args(["-batch", "-nx", "-ex", "set pagination off", "-ex"])
+    .arg(format!("echo {MARKER}\\n"))
+    .args(["-ex", "bt", crash.program])
+    .arg(core.as_path())
+    .current_dir(crash.cwd)
+    .stdin(Stdio::null());
+  run_gdb(&mut gdb)
+}
+
+// The frames gdb printed after the marker. Long frames continue on
+// indented lines, those are joined back.
+fn run_gdb(gdb: &mut Command) -> Vec<String> {
+  let output = match gdb.output() {
+    Ok(output) => output,
+    Err(e) => {
+      eprint!("{}", toolchain::spawn_error("gdb", e));
+      return Vec::new();
+    }
+  };
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let mut frames: Vec<String> = Vec::new();
+  for line in stdout.lines().skip_while(|line| *line != MARKER).skip(1) {
+    if line.starts_with('#') {
+      frames.push(line.to_string());
+    } else if line.starts_with(' ') {
+      if let Some(frame) = frames.last_mut() {
+        frame.push(' ');
+        frame.push_str(line.trim());
+      }
+    }
+  }
+  frames
+}
+
+// The newest core file written since the program started, where the
+// kernel's core_pattern puts them. Cores piped to a handler like
+// systemd-coredump can't be found this way.
+fn find_core(crash: &Crash) -> Option<PathBuf> {
+  let pattern = fs::read_to_string("/proc/sys/kernel/core_pattern").unwrap_or_else(|_| String::from("core"));
+  let pattern = pattern.trim();
+  if pattern.starts_with('|') {
+    return None;
+  }
+  let pattern = match crash.pid {
+    Some(pid) => pattern.replace("%p", pid.to_string().as_str()),
+    None => pattern.to_string(),
+  };
+  let pattern = Path::new(crash.cwd).join(pattern);
+  let dir = pattern.parent()?;
+  let file = pattern.file_name()?.to_string_lossy().to_string();
+  let prefix = file.split('%').next().unwrap_or("");
+  // File times can be coarser than the clock, a program that crashes right
+  // away may leave a core that looks slightly older than its start.
+  let since = crash.started - Duration::from_secs(1);
+
+  fs::read_dir(dir)
+    .ok()?
+    .flatten()
+    .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+    .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+    .filter(|(modified, path)| *modified >= since && path.is_file())
+    .max()
+    .map(|(_, path)| path)
+}
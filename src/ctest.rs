@@ -0,0 +1,221 @@
+use crate::json;
+use crate::output::Event;
+
+use std::process::Command;
+use std::process::Stdio;
+use std::vec::Vec;
+
+pub struct TestResult {
+  pub name: String,
+  pub status: String,
+  pub duration_ms: u64,
+}
+
+impl TestResult {
+  pub fn passed(&self) -> bool {
+    self.status == "passed"
+  }
+
+  pub fn emit_event(&self) {
+    Event::new("test-result")
+      .with("name", self.name.as_str())
+      .with("status", self.status.as_str())
+      .with("duration_ms", self.duration_ms)
+      .emit();
+  }
+}
+
+// Parses ctest's per-test summary line, e.g.
+//   1/3 Test #1: HelloTest.BasicAssertions ....   Passed    0.01 sec
+//   2/3 Test #2: HelloTest.Other ..............***Failed    0.02 sec
+// or the same with `MemCheck` in place of `Test` under `-T memcheck`. With
+// ten tests or more the numbers are padded, as in ` 1/12 Test  #1:`.
+pub fn parse_result_line(line: &str) -> Option<TestResult> {
+  let rest = [" Test ", " MemCheck "].iter().find_map(|marker| {
+    let idx = line.find(marker)?;
+    line[idx + marker.len()..].trim_start().strip_prefix('#')
+  })?;
+  let (_, rest) = rest.split_once(": ")?;
+  let (name, rest) = rest.split_once(' ')?;
+  let rest = rest.trim_start_matches(['.', ' ']).trim_start_matches('*');
+
+  let seconds_idx = rest.rfind(" sec")?;
+  let before_seconds = rest[..seconds_idx].trim_end();
+  let (outcome, seconds) = before_seconds.rsplit_once(' ')?;
+  let seconds: f64 = seconds.parse().ok()?;
+
+  let outcome = outcome.trim();
+  let status = if outcome == "Passed" {
+    "passed"
+  } else if outcome.starts_with("Not Run") || outcome.starts_with("Skipped") {
+    "skipped"
+  } else if outcome.starts_with("Timeout") {
+    "timeout"
+  } else if outcome.starts_with("Exception") {
+    "crashed"
+  } else {
+    "failed"
+  };
+
+  Some(TestResult {
+    name: name.to_string(),
+    status: status.to_string(),
+    duration_ms: (seconds * 1000.0).round() as u64,
+  })
+}
+
+// How ctest runs one test.
+pub struct TestCommand {
+  pub name: String,
+  pub command: Vec<String>,
+  pub cwd: String,
+}
+
+// Which tests `gojo test` runs, as ctest regular expressions.
+#[derive(Default)]
+pub struct Filter {
+  // Test names to run, `-R`.
+  pub pattern: Option<String>,
+  // Test names to leave out, `-E`.
+  pub exclude: Option<String>,
+  // Labels to run, `-L`.
+  pub label: Option<String>,
+}
+
+impl Filter {
+  // The ctest options selecting the tests. With `names` false only labels
+  // are filtered, for tests that are whole GoogleTest binaries, where the
+  // names are passed on as a --gtest_filter instead.
+  pub fn args(&self, names: bool) -> Vec<String> {
+    let mut options = vec![("-L", &self.label)];
+    if names {
+      options.extend([("-R", &self.pattern), ("-E", &self.exclude)]);
+    }
+    let mut args: Vec<String> = Vec::new();
+    for (option, value) in options {
+      if let Some(value) = value {
+        args.push(option.to_string());
+        args.push(value.clone());
+      }
+    }
+    args
+  }
+}
+
+// The tests registered in `build_dir` that `filter_args` select, from
+// `ctest --show-only=json-v1`.
+pub fn commands(build_dir: &str, filter_args: &[String]) -> Vec<TestCommand> {
+  let Ok(output) = Command::new("ctest")
+    .args(["--show-only=json-v1"])
+    .args(filter_args)
+    .current_dir(build_dir)
+    .stdin(Stdio::null())
+    .stderr(Stdio::null())
+    .output()
+  else {
+    return Vec::new();
+  };
+  let Some(root) = json::parse(String::from_utf8_lossy(&output.stdout).as_ref()) else {
+    return Vec::new();
+  };
+  let mut commands: Vec<TestCommand> = Vec::new();
+  for test in root.get("tests").and_then(|tests| tests.as_array()).into_iter().flatten() {
+    let Some(name) = test.get("name").and_then(|name| name.as_str()) else {
+      continue;
+    };
+    let command: Vec<String> = test
+      .get("command")
+      .and_then(|command| command.as_array())
+      .map(|command| command.iter().filter_map(|word| word.as_str()).map(String::from).collect())
+      .unwrap_or_default();
+    if command.is_empty() {
+      continue;
+    }
+    let cwd = test
+      .get("properties")
+      .and_then(|properties| properties.as_array())
+      .and_then(|properties| {
+        properties.iter().find(|property| property.get("name").and_then(|name| name.as_str()) == Some("WORKING_DIRECTORY"))
+      })
+      .and_then(|property| property.get("value"))
+      .and_then(|value| value.as_str())
+      .unwrap_or(build_dir);
+    commands.push(TestCommand { name: name.to_string(), command, cwd: cwd.to_string() });
+  }
+  commands
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(line: &str) -> (String, String, u64) {
+    let result = parse_result_line(line).unwrap();
+    (result.name, result.status, result.duration_ms)
+  }
+
+  #[test]
+  fn parses_outcomes() {
+    assert_eq!(
+      parse("1/6 Test #1: HelloTest.BasicAssertions ..........   Passed    0.01 sec"),
+      (String::from("HelloTest.BasicAssertions"), String::from("passed"), 10)
+    );
+    assert_eq!(
+      parse("2/6 Test #2: HelloTest.Fails ....................***Failed    0.02 sec"),
+      (String::from("HelloTest.Fails"), String::from("failed"), 20)
+    );
+    assert_eq!(
+      parse("3/6 Test #3: needs_data .........................***Not Run   0.00 sec"),
+      (String::from("needs_data"), String::from("skipped"), 0)
+    );
+    assert_eq!(
+      parse("4/6 Test #4: Crash.NullPointer ..................***Exception: SegFault  0.12 sec"),
+      (String::from("Crash.NullPointer"), String::from("crashed"), 120)
+    );
+    assert_eq!(
+      parse("5/6 Test #5: Slow.Loop ..........................***Timeout   1.50 sec"),
+      (String::from("Slow.Loop"), String::from("timeout"), 1500)
+    );
+    assert_eq!(
+      parse("6/6 Test #6: Fixture.Skips ......................   Skipped   0.00 sec"),
+      (String::from("Fixture.Skips"), String::from("skipped"), 0)
+    );
+  }
+
+  #[test]
+  fn parses_padded_numbers() {
+    assert_eq!(
+      parse(" 1/12 Test  #1: Table.Grows ........................   Passed    0.00 sec").0,
+      String::from("Table.Grows")
+    );
+    assert_eq!(
+      parse("12/12 Test #12: Table.Shrinks ......................***Failed    0.00 sec").0,
+      String::from("Table.Shrinks")
+    );
+  }
+
+  #[test]
+  fn parses_memcheck_lines() {
+    assert_eq!(
+      parse(" 1/12 MemCheck  #1: Table.Grows .....................   Passed    0.83 sec").1,
+      String::from("passed")
+    );
+    assert_eq!(
+      parse("2/2 MemCheck #2: Table.Leaks ........................***Failed    1.21 sec"),
+      (String::from("Table.Leaks"), String::from("failed"), 1210)
+    );
+  }
+
+  #[test]
+  fn ignores_other_lines() {
+    for line in [
+      "    Start 1: HelloTest.BasicAssertions",
+      "100% tests passed, 0 tests failed out of 6",
+      "Total Test time (real) =   1.70 sec",
+      "\t  2 - HelloTest.Fails (Failed)",
+      "",
+    ] {
+      assert!(parse_result_line(line).is_none(), "{line}");
+    }
+  }
+}
@@ -0,0 +1,72 @@
+use crate::error::GojoError;
+use crate::toolchain;
+
+use std::io::Result;
+use std::path::Path;
+use std::process::Command;
+
+// The debugger to launch: the one set as `debugger` in the config,
+// otherwise gdb when it is installed and lldb after it.
+pub fn choose(configured: &str) -> Result<String> {
+  if !configured.is_empty() {
+    return Ok(configured.to_string());
+  }
+  for debugger in ["gdb", "lldb"] {
+    if toolchain::find_program(debugger).is_some() {
+      return Ok(debugger.to_string());
+    }
+  }
+  Err(GojoError::ToolMissing(String::from(
+    "\x1b[31mtool not found:\x1b[0m\n\tneither 'gdb' nor 'lldb' is installed or on your PATH\n\tset 'debugger' in .gojo to use another one\n",
+  ))
+  .into())
+}
+
+fn is_lldb(debugger: &str) -> bool {
+  Path::new(debugger).file_name().map(|name| name.to_string_lossy().contains("lldb")).unwrap_or(false)
+}
+
+// `debugger` set up to start `program` with `args` from `cwd`, with a
+// breakpoint at each `file:line`. The program is loaded but not started,
+// so more breakpoints can be set before typing `run`.
+pub fn command(debugger: &str, program: &str, args: &[String], cwd: &str, breakpoints: &[String]) -> Command {
+  let mut command = Command::new(debugger);
+  command.current_dir(cwd);
+  if is_lldb(debugger) {
+    for breakpoint in breakpoints {
+      command.args(["-o", format!("breakpoint set --file {} --line {}", file_of(breakpoint), line_of(breakpoint)).as_str()]);
+    }
+    command.args(["--", program]).args(args);
+  } else {
+    command.arg("-q");
+    // Breakpoints in shared libraries that aren't loaded yet are kept
+    // instead of prompting.
+    command.args(["-ex", "set breakpoint pending on"]);
+    for breakpoint in breakpoints {
+      command.args(["-ex", format!("break {breakpoint}").as_str()]);
+    }
+    command.args(["--args", program]).args(args);
+  }
+  command
+}
+
+fn file_of(breakpoint: &str) -> &str {
+  breakpoint.rsplit_once(':').map(|(file, _)| file).unwrap_or(breakpoint)
+}
+
+fn line_of(breakpoint: &str) -> &str {
+  breakpoint.rsplit_once(':').map(|(_, line)| line).unwrap_or("")
+}
+
+// `--break` values must be `file:line`.
+pub fn parse_breakpoint(value: &str) -> Result<String> {
+  match value.rsplit_once(':') {
+    Some((file, line)) if !file.is_empty() && line.parse::<u32>().is_ok() => Ok(value.to_string()),
+    _ => Err(
+      GojoError::Usage(format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tinvalid breakpoint '{value}', expected <file>:<line>\n\tsee 'gojo debug --help'\n"
+      ))
+      .into(),
+    ),
+  }
+}
@@ -0,0 +1,136 @@
+use crate::hash;
+use crate::output;
+use crate::output::Event;
+use crate::toolchain;
+use crate::error::GojoError;
+
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+use std::vec::Vec;
+
+// Executables land in `~/.gojo/bin`, next to the installed packages.
+pub fn default_prefix() -> String {
+  match env::home_dir() {
+    Some(home) => format!("{}/.gojo", home.to_string_lossy()),
+    None => String::from(".gojo"),
+  }
+}
+
+// `cmake --install` resolves a relative prefix against its own working
+// directory, the recorded paths must not depend on it.
+pub fn absolute(prefix: &str) -> Result<String> {
+  if Path::new(prefix).is_absolute() {
+    return Ok(prefix.to_string());
+  }
+  Ok(env::current_dir()?.join(prefix).to_string_lossy().to_string())
+}
+
+// What was deployed is recorded outside the build directory, so cleaning a
+// build never loses track of installed files.
+fn manifest_path(project_root: &str) -> String {
+  let dir = env::home_dir().map(|home| home.to_string_lossy().to_string()).unwrap_or_default();
+  format!("{dir}/.gojo/deployments/{}.txt", hash::fnv1a_hex(project_root.as_bytes()))
+}
+
+fn read_manifest(path: &str) -> Vec<String> {
+  fs::read_to_string(path)
+    .map(|contents| contents.lines().filter(|line| !line.is_empty()).map(String::from).collect())
+    .unwrap_or_default()
+}
+
+fn write_manifest(path: &str, files: &[String]) -> Result<()> {
+  if let Some(dir) = Path::new(path).parent() {
+    fs::create_dir_all(dir)?;
+  }
+  fs::write(path, files.iter().map(|file| format!("{file}\n")).collect::<String>())
+}
+
+pub fn install(project_root: &str, build_dir: &str, prefix: &str) -> Result<()> {
+  status!("\x1b[1;35mDeploying\x1b[0m to {}\n\n", prefix);
+  let status = Command::new("cmake")
+    .args(["--install", build_dir, "--prefix", prefix])
+    .stdout(output::child_stdout())
+    .stderr(Stdio::inherit())
+    .status()
+    .map_err(|e| toolchain::spawn_error("cmake", e))?;
+  if !status.success() {
+    return Err(GojoError::Failed(
+      "\x1b[31mCMake failed to install project\x1b[0m\n\n".to_string(),
+    ).into());
+  }
+
+  // Earlier deploys, possibly to other prefixes, stay recorded until the
+  // project is undeployed.
+  let installed = read_manifest(format!("{build_dir}/install_manifest.txt").as_str());
+  let manifest = manifest_path(project_root);
+  let mut files = read_manifest(manifest.as_str());
+  for file in installed.iter() {
+    if !files.contains(file) {
+      files.push(file.clone());
+    }
+  }
+  write_manifest(manifest.as_str(), &files)?;
+
+  status!("\n\x1b[1;32mDeployed\x1b[0m {} files, 'gojo undeploy' removes them\n\n", installed.len());
+  Event::new("deploy-finished")
+    .with("prefix", prefix)
+    .with("files", installed.len())
+    .emit();
+  Ok(())
+}
+
+// Removes the files every deploy of the project installed and nothing else.
+// Files that can't be removed stay recorded so a later undeploy retries them.
+pub fn uninstall(project_root: &str) -> Result<()> {
+  let manifest = manifest_path(project_root);
+  let files = read_manifest(manifest.as_str());
+  if files.is_empty() {
+    return Err(GojoError::Failed(
+      "\x1b[31mnothing to undeploy:\x1b[0m\n\tthis project has not been deployed with 'gojo deploy'\n".to_string(),
+    ).into());
+  }
+
+  let mut removed = 0;
+  let mut missing = 0;
+  let mut remaining: Vec<String> = Vec::new();
+  for file in files {
+    match fs::remove_file(file.as_str()) {
+      Ok(()) => {
+        status!("  \x1b[1;35mRemoved\x1b[0m {}\n", file);
+        removed += 1;
+      }
+      Err(e) if e.kind() == ErrorKind::NotFound => {
+        missing += 1;
+      }
+      Err(e) => {
+        eprintln!("  \x1b[31mfailed to remove\x1b[0m {}: {}", file, e);
+        remaining.push(file);
+      }
+    }
+  }
+
+  Event::new("undeploy-finished")
+    .with("removed", removed as u64)
+    .with("missing", missing as u64)
+    .with("failed", remaining.len())
+    .emit();
+  if !remaining.is_empty() {
+    write_manifest(manifest.as_str(), &remaining)?;
+    return Err(GojoError::Failed(
+      format!("\x1b[31mundeploy incomplete:\x1b[0m\n\t{} files could not be removed\n", remaining.len()),
+    ).into());
+  }
+  fs::remove_file(manifest)?;
+
+  status!("\n\x1b[1;32mUndeployed\x1b[0m {} files", removed);
+  if missing > 0 {
+    status!(", {} were already gone", missing);
+  }
+  status!("\n\n");
+  Ok(())
+}
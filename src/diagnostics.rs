@@ -0,0 +1,350 @@
+use crate::json::Value;
+use crate::output;
+use crate::output::Event;
+
+use std::collections::HashSet;
+use std::fs;
+use std::vec::Vec;
+
+// Number of diagnostics listed per file and severity before the summary
+// collapses the rest into a count.
+const MAX_LISTED: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+  Error,
+  Warning,
+}
+
+impl Severity {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Severity::Error => "error",
+      Severity::Warning => "warning",
+    }
+  }
+
+  fn color(&self) -> &'static str {
+    match self {
+      Severity::Error => "\x1b[1;31m",
+      Severity::Warning => "\x1b[1;33m",
+    }
+  }
+}
+
+pub struct Diagnostic {
+  pub file: String,
+  pub line: u32,
+  pub column: u32,
+  pub severity: Severity,
+  pub message: String,
+  // Source snippet, caret and note lines that follow the diagnostic.
+  pub context: Vec<String>,
+}
+
+pub struct Diagnostics {
+  pub items: Vec<Diagnostic>,
+  pub duplicates: usize,
+}
+
+impl Diagnostics {
+  pub fn count(&self, severity: Severity) -> usize {
+    self.items.iter().filter(|d| d.severity == severity).count()
+  }
+
+  pub fn emit_events(&self) {
+    for diagnostic in self.items.iter() {
+      Event::new("diagnostic")
+        .with("file", diagnostic.file.as_str())
+        .with("line", diagnostic.line)
+        .with("column", diagnostic.column)
+        .with("severity", diagnostic.severity.as_str())
+        .with("message", diagnostic.message.as_str())
+        .with("context", Value::from(diagnostic.context.clone()))
+        .emit();
+    }
+  }
+}
+
+// A report from a static analyzer. Analyzers use their own severity names,
+// so unlike `Diagnostic` the severity is kept as reported.
+pub struct Finding {
+  pub file: String,
+  pub line: u32,
+  pub column: u32,
+  pub severity: String,
+  pub message: String,
+  pub id: Option<String>,
+}
+
+impl Finding {
+  pub fn emit_event(&self, tool: &str) {
+    Event::new("check-finding")
+      .with("tool", tool)
+      .with("file", self.file.as_str())
+      .with("line", self.line)
+      .with("column", self.column)
+      .with("severity", self.severity.as_str())
+      .with("message", self.message.as_str())
+      .with("id", self.id.clone())
+      .emit();
+  }
+}
+
+const COMPILER_KINDS: &[&str] = &["fatal error", "error", "warning", "note"];
+const ANALYZER_KINDS: &[&str] = &[
+  "fatal error",
+  "error",
+  "warning",
+  "style",
+  "performance",
+  "portability",
+  "information",
+];
+
+// Parses GCC and Clang diagnostics out of raw compiler output. The same
+// header diagnostic reported by several translation units is kept once.
+pub fn parse(output: &[String]) -> Diagnostics {
+  let mut items: Vec<Diagnostic> = Vec::new();
+  let mut seen: HashSet<(String, u32, u32, Severity, String)> = HashSet::new();
+  let mut duplicates = 0;
+  // Whether context lines should be attached to the last item in `items`.
+  let mut attach = false;
+
+  for raw in output {
+    let line = output::strip_ansi(raw);
+
+    if let Some((file, line_no, column, kind, message)) = parse_location_line(line.as_str(), COMPILER_KINDS) {
+      let severity = match kind {
+        "error" | "fatal error" => Some(Severity::Error),
+        "warning" => Some(Severity::Warning),
+        _ => None,
+      };
+
+      match severity {
+        Some(severity) => {
+          let file = normalize_path(file);
+          let key = (file.clone(), line_no, column, severity, message.to_string());
+          if seen.contains(&key) {
+            duplicates += 1;
+            attach = false;
+            continue;
+          }
+          seen.insert(key);
+          items.push(Diagnostic {
+            file,
+            line: line_no,
+            column,
+            severity,
+            message: message.to_string(),
+            context: Vec::new(),
+          });
+          attach = true;
+        }
+        None => {
+          // Notes belong to the diagnostic they explain.
+          if attach {
+            items.last_mut().unwrap().context.push(line.trim_end().to_string());
+          }
+        }
+      }
+      continue;
+    }
+
+    if let Some((file, message)) = parse_linker_line(line.as_str()) {
+      let key = (file.clone(), 0, 0, Severity::Error, message.clone());
+      if seen.contains(&key) {
+        duplicates += 1;
+        attach = false;
+        continue;
+      }
+      seen.insert(key);
+      items.push(Diagnostic {
+        file,
+        line: 0,
+        column: 0,
+        severity: Severity::Error,
+        message,
+        context: Vec::new(),
+      });
+      attach = false;
+      continue;
+    }
+
+    if attach && is_context_line(line.as_str()) {
+      items.last_mut().unwrap().context.push(line.trim_end().to_string());
+    } else {
+      attach = false;
+    }
+  }
+
+  Diagnostics { items, duplicates }
+}
+
+// Parses cppcheck and clang-tidy style reports, where a trailing
+// `[identifier]` names the check that fired.
+pub fn parse_findings(output: &[String]) -> Vec<Finding> {
+  let mut findings: Vec<Finding> = Vec::new();
+  let mut seen: HashSet<(String, u32, u32, String)> = HashSet::new();
+
+  for raw in output {
+    let line = output::strip_ansi(raw);
+    let Some((file, line_no, column, kind, message)) = parse_location_line(line.as_str(), ANALYZER_KINDS) else {
+      continue;
+    };
+    let file = normalize_path(file);
+    if !seen.insert((file.clone(), line_no, column, message.to_string())) {
+      continue;
+    }
+
+    let mut id = None;
+    let mut text = message;
+    if message.ends_with(']') {
+      if let Some(open) = message.rfind(" [") {
+        id = Some(message[open + 2..message.len() - 1].to_string());
+        text = &message[..open];
+      }
+    }
+    findings.push(Finding {
+      file,
+      line: line_no,
+      column,
+      severity: kind.to_string(),
+      message: text.to_string(),
+      id,
+    });
+  }
+  findings
+}
+
+pub fn print_summary(diagnostics: &Diagnostics) {
+  if diagnostics.items.is_empty() {
+    return;
+  }
+
+  let first_error = diagnostics.items.iter().find(|d| d.severity == Severity::Error);
+  if let Some(error) = first_error {
+    println!("\n\x1b[1;31merror:\x1b[0m \x1b[1m{}\x1b[0m", error.message);
+    println!("  \x1b[1;34m-->\x1b[0m {}", location(error));
+    for line in error.context.iter() {
+      println!("  {line}");
+    }
+  }
+
+  let mut files: Vec<&str> = Vec::new();
+  for diagnostic in diagnostics.items.iter() {
+    if !files.contains(&diagnostic.file.as_str()) {
+      files.push(diagnostic.file.as_str());
+    }
+  }
+
+  println!("\n\x1b[1;35mDiagnostics by file:\x1b[0m");
+  for file in files {
+    println!("  \x1b[1;36m{file}\x1b[0m");
+    for severity in [Severity::Error, Severity::Warning] {
+      let in_file: Vec<&Diagnostic> = diagnostics
+        .items
+        .iter()
+        .filter(|d| d.file == file && d.severity == severity)
+        .collect();
+      for diagnostic in in_file.iter().take(MAX_LISTED) {
+        println!(
+          "    {}{}\x1b[0m {}:{} {}",
+          severity.color(),
+          severity.as_str(),
+          diagnostic.line,
+          diagnostic.column,
+          diagnostic.message
+        );
+      }
+      if in_file.len() > MAX_LISTED {
+        println!("    ... and {} more {}s", in_file.len() - MAX_LISTED, severity.as_str());
+      }
+    }
+  }
+
+  let errors = diagnostics.count(Severity::Error);
+  let warnings = diagnostics.count(Severity::Warning);
+  print!(
+    "\n\x1b[1;31m{} {}\x1b[0m, \x1b[1;33m{} {}\x1b[0m",
+    errors,
+    plural(errors, "error"),
+    warnings,
+    plural(warnings, "warning")
+  );
+  if diagnostics.duplicates > 0 {
+    print!(" ({} duplicates suppressed)", diagnostics.duplicates);
+  }
+  println!();
+}
+
+fn location(diagnostic: &Diagnostic) -> String {
+  if diagnostic.line == 0 {
+    return diagnostic.file.clone();
+  }
+  format!("{}:{}:{}", diagnostic.file, diagnostic.line, diagnostic.column)
+}
+
+fn plural(count: usize, word: &str) -> String {
+  if count == 1 {
+    return word.to_string();
+  }
+  format!("{word}s")
+}
+
+// Matches `file:line:col: kind: message` and `file:line: kind: message`.
+fn parse_location_line<'a>(line: &'a str, kinds: &[&'static str]) -> Option<(&'a str, u32, u32, &'static str, &'a str)> {
+  for &kind in kinds {
+    let marker = format!(": {kind}: ");
+    let Some(idx) = line.find(marker.as_str()) else {
+      continue;
+    };
+    let head = &line[..idx];
+    let message = line[idx + marker.len()..].trim();
+
+    let mut parts = head.rsplitn(3, ':');
+    let last = parts.next()?;
+    let middle = parts.next()?;
+    let last_number = last.parse::<u32>().ok()?;
+    if let Ok(line_no) = middle.parse::<u32>() {
+      let file = parts.next()?;
+      return Some((file, line_no, last_number, kind, message));
+    }
+    let file = &head[..head.len() - last.len() - 1];
+    return Some((file, last_number, 0, kind, message));
+  }
+  None
+}
+
+// Matches unresolved symbols and the linker driver's final failure line.
+fn parse_linker_line(line: &str) -> Option<(String, String)> {
+  if let Some(idx) = line.find("undefined reference to") {
+    let file = line[..idx].split(":(").next().unwrap_or("").trim_end_matches(": ");
+    return Some((normalize_path(file), line[idx..].trim().to_string()));
+  }
+  for prefix in ["collect2: error: ", "clang: error: ", "clang++: error: "] {
+    if let Some(message) = line.strip_prefix(prefix) {
+      return Some((String::from("linker"), message.trim().to_string()));
+    }
+  }
+  None
+}
+
+fn is_context_line(line: &str) -> bool {
+  line.starts_with(' ') && !line.trim().is_empty()
+}
+
+// Resolves relative paths emitted from inside the build tree and shortens
+// anything under the current project to a project relative path.
+fn normalize_path(file: &str) -> String {
+  let canonical = fs::canonicalize(file)
+    .map(|p| p.to_string_lossy().to_string())
+    .unwrap_or_else(|_| file.to_string());
+  let root = std::env::current_dir()
+    .map(|p| p.to_string_lossy().to_string() + "/")
+    .unwrap_or_default();
+  match canonical.strip_prefix(root.as_str()) {
+    Some(relative) => relative.to_string(),
+    None => canonical,
+  }
+}
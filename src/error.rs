@@ -0,0 +1,106 @@
+use std::fmt;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+// Why a command failed. Each kind exits gojo with its own code so scripts
+// can tell them apart. Commands still return `io::Result`, these travel
+// inside the `io::Error` and `exit_code` recovers them in main. Messages
+// are printed as they are.
+#[derive(Debug)]
+pub enum GojoError {
+  // Unknown command, option or option value.
+  Usage(String),
+  // Missing or invalid .gojo, or a project gojo can't work with.
+  Config(String),
+  // A program gojo needs is not installed.
+  ToolMissing(String),
+  ConfigureFailed(String),
+  BuildFailed(String),
+  TestFailed(String),
+  CheckFailed(String),
+  // Anything else, like a failed git or CPack step.
+  Failed(String),
+  // The user's program exited unsuccessfully, gojo exits the same way.
+  Exited(i32),
+}
+
+pub const EXIT_FAILED: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_CONFIG: i32 = 3;
+pub const EXIT_TOOL_MISSING: i32 = 4;
+pub const EXIT_CONFIGURE_FAILED: i32 = 5;
+pub const EXIT_BUILD_FAILED: i32 = 6;
+pub const EXIT_TEST_FAILED: i32 = 7;
+pub const EXIT_CHECK_FAILED: i32 = 8;
+
+impl GojoError {
+  pub fn code(&self) -> i32 {
+    match self {
+      GojoError::Usage(_) => EXIT_USAGE,
+      GojoError::Config(_) => EXIT_CONFIG,
+      GojoError::ToolMissing(_) => EXIT_TOOL_MISSING,
+      GojoError::ConfigureFailed(_) => EXIT_CONFIGURE_FAILED,
+      GojoError::BuildFailed(_) => EXIT_BUILD_FAILED,
+      GojoError::TestFailed(_) => EXIT_TEST_FAILED,
+      GojoError::CheckFailed(_) => EXIT_CHECK_FAILED,
+      GojoError::Failed(_) => EXIT_FAILED,
+      GojoError::Exited(code) => *code,
+    }
+  }
+
+  // An error for a child that didn't succeed, with the exit code a shell
+  // would report for it: its own, or 128 plus the signal that killed it.
+  pub fn exited(status: ExitStatus) -> GojoError {
+    match signal(&status) {
+      Some(signal) => GojoError::Exited(128 + signal),
+      None => GojoError::Exited(status.code().unwrap_or(1)),
+    }
+  }
+}
+
+// The signal that killed a child, on platforms that have signals.
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub fn signal(status: &ExitStatus) -> Option<i32> {
+  #[cfg(unix)]
+  {
+    status.signal()
+  }
+  #[cfg(not(unix))]
+  {
+    None
+  }
+}
+
+impl fmt::Display for GojoError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      GojoError::Usage(message)
+      | GojoError::Config(message)
+      | GojoError::ToolMissing(message)
+      | GojoError::ConfigureFailed(message)
+      | GojoError::BuildFailed(message)
+      | GojoError::TestFailed(message)
+      | GojoError::CheckFailed(message)
+      | GojoError::Failed(message) => write!(f, "{message}"),
+      GojoError::Exited(code) => writeln!(f, "\x1b[31mprocess exited with code {code}\x1b[0m"),
+    }
+  }
+}
+
+impl std::error::Error for GojoError {}
+
+impl From<GojoError> for io::Error {
+  fn from(error: GojoError) -> io::Error {
+    io::Error::other(error)
+  }
+}
+
+// Plain I/O errors, like a file gojo couldn't write, are general failures.
+pub fn exit_code(error: &io::Error) -> i32 {
+  match error.get_ref().and_then(|inner| inner.downcast_ref::<GojoError>()) {
+    Some(error) => error.code(),
+    None => EXIT_FAILED,
+  }
+}
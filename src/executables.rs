@@ -0,0 +1,238 @@
+use crate::error::GojoError;
+use crate::json;
+use crate::json::Value;
+
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use std::vec::Vec;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Role {
+  Binary,
+  Example,
+  Test,
+}
+
+// An executable the build produces, as `gojo run` sees it.
+pub struct Executable {
+  pub name: String,
+  // The build target that produces it.
+  pub target: String,
+  pub path: String,
+  pub role: Role,
+}
+
+// Examples are built from examples/, test binaries from test/ or tests/.
+pub fn role_of(sources: &[&str]) -> Role {
+  if !sources.is_empty() && sources.iter().all(|source| source.starts_with("examples/")) {
+    return Role::Example;
+  }
+  if sources.iter().any(|source| source.starts_with("test/") || source.starts_with("tests/")) {
+    return Role::Test;
+  }
+  Role::Binary
+}
+
+// `examples/<name>.<ext>` files and `examples/<name>/` directories, as
+// names and sources relative to the project root.
+pub fn find_examples(project_root: &str, cpp: &str) -> Vec<(String, String)> {
+  let mut examples: Vec<(String, String)> = Vec::new();
+  let Ok(entries) = fs::read_dir(format!("{project_root}/examples")) else {
+    return examples;
+  };
+  for entry in entries.flatten() {
+    let file = entry.file_name().to_string_lossy().to_string();
+    if entry.path().is_dir() {
+      examples.push((file.clone(), format!("examples/{file}")));
+    } else if let Some(name) = file.strip_suffix(format!(".{cpp}").as_str()) {
+      examples.push((name.to_string(), format!("examples/{file}")));
+    }
+  }
+  examples.sort();
+  examples
+}
+
+const QUERY: &str = ".cmake/api/v1/query/codemodel-v2";
+const REPLY_DIR: &str = ".cmake/api/v1/reply";
+
+// Asks CMake to describe the build's targets through its file API on the
+// next configure.
+pub fn request_codemodel(build_dir: &str) -> Result<()> {
+  let query = format!("{build_dir}/{QUERY}");
+  if let Some(dir) = Path::new(query.as_str()).parent() {
+    fs::create_dir_all(dir)?;
+  }
+  fs::write(query, "")
+}
+
+pub fn has_codemodel(build_dir: &str) -> bool {
+  latest_index(build_dir).is_some()
+}
+
+fn latest_index(build_dir: &str) -> Option<String> {
+  fs::read_dir(format!("{build_dir}/{REPLY_DIR}"))
+    .ok()?
+    .flatten()
+    .map(|entry| entry.file_name().to_string_lossy().to_string())
+    .filter(|name| name.starts_with("index-") && name.ends_with(".json"))
+    .max()
+}
+
+fn read_reply(build_dir: &str, file: &str) -> Option<Value> {
+  json::parse(fs::read_to_string(format!("{build_dir}/{REPLY_DIR}/{file}")).ok()?.as_str())
+}
+
+// The executable targets from the codemodel CMake wrote at the last
+// configure.
+pub fn from_codemodel(build_dir: &str) -> Result<Vec<Executable>> {
+  let missing = || -> std::io::Error {
+    GojoError::Failed(format!(
+      "\x1b[31mno target information found:\x1b[0m\n\tCMake has not described the targets in {build_dir}, run 'gojo build' first\n"
+    ))
+    .into()
+  };
+  let index = latest_index(build_dir).and_then(|index| read_reply(build_dir, index.as_str())).ok_or_else(missing)?;
+  let codemodel_file = index
+    .get("objects")
+    .and_then(|objects| objects.as_array())
+    .and_then(|objects| objects.iter().find(|object| object.get("kind").and_then(|kind| kind.as_str()) == Some("codemodel")))
+    .and_then(|object| object.get("jsonFile"))
+    .and_then(|file| file.as_str())
+    .ok_or_else(missing)?;
+  let codemodel = read_reply(build_dir, codemodel_file).ok_or_else(missing)?;
+
+  let mut executables: Vec<Executable> = Vec::new();
+  let no_targets: Vec<Value> = Vec::new();
+  let targets = codemodel
+    .get("configurations")
+    .and_then(|configurations| configurations.as_array())
+    .and_then(|configurations| configurations.first())
+    .and_then(|configuration| configuration.get("targets"))
+    .and_then(|targets| targets.as_array())
+    .unwrap_or(&no_targets);
+  for target in targets.iter() {
+    let Some(target) = target.get("jsonFile").and_then(|file| file.as_str()).and_then(|file| read_reply(build_dir, file)) else {
+      continue;
+    };
+    if target.get("type").and_then(|kind| kind.as_str()) != Some("EXECUTABLE") {
+      continue;
+    }
+    let Some(artifact) = target
+      .get("artifacts")
+      .and_then(|artifacts| artifacts.as_array())
+      .and_then(|artifacts| artifacts.first())
+      .and_then(|artifact| artifact.get("path"))
+      .and_then(|path| path.as_str())
+    else {
+      continue;
+    };
+    // Generated sources, like the precompiled header's, say nothing about
+    // what the target is.
+    let sources: Vec<&str> = target
+      .get("sources")
+      .and_then(|sources| sources.as_array())
+      .map(|sources| {
+        sources
+          .iter()
+          .filter(|source| !matches!(source.get("isGenerated"), Some(Value::Bool(true))))
+          .filter_map(|source| source.get("path").and_then(|path| path.as_str()))
+          .collect()
+      })
+      .unwrap_or_default();
+
+    let role = role_of(&sources);
+    let target_name = target.get("name").and_then(|name| name.as_str()).unwrap_or_default().to_string();
+    let path = if Path::new(artifact).is_absolute() { artifact.to_string() } else { format!("{build_dir}/{artifact}") };
+    // Examples are known by their file name, their target names are
+    // prefixed to stay clear of the project's own.
+    let name = match role {
+      Role::Example => Path::new(artifact).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+      _ => target_name.clone(),
+    };
+    executables.push(Executable { name, target: target_name, path, role });
+  }
+  executables.sort_by(|a, b| a.name.cmp(&b.name));
+  Ok(executables)
+}
+
+// Picks what `gojo run` launches: the named binary or example, otherwise
+// the project's executable or the only binary there is.
+pub fn select<'a>(
+  executables: &'a [Executable],
+  bin: Option<&str>,
+  example: Option<&str>,
+  project_name: &str,
+) -> Result<&'a Executable> {
+  let names = |role: Role| -> String {
+    let names: Vec<&str> = executables.iter().filter(|e| e.role == role).map(|e| e.name.as_str()).collect();
+    if names.is_empty() {
+      return String::from("none");
+    }
+    names.join(", ")
+  };
+
+  if let Some(example) = example {
+    return executables.iter().find(|e| e.role == Role::Example && e.name == example).ok_or_else(|| {
+      GojoError::Usage(format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tno example named '{example}'\n\tavailable examples: {}\n",
+        names(Role::Example)
+      ))
+      .into()
+    });
+  }
+  if let Some(bin) = bin {
+    return executables.iter().find(|e| e.role == Role::Binary && e.name == bin).ok_or_else(|| {
+      GojoError::Usage(format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tno binary named '{bin}'\n\tavailable binaries: {}\n",
+        names(Role::Binary)
+      ))
+      .into()
+    });
+  }
+
+  let binaries: Vec<&Executable> = executables.iter().filter(|e| e.role == Role::Binary).collect();
+  if let Some(project) = binaries.iter().find(|e| e.name == project_name) {
+    return Ok(project);
+  }
+  match binaries.as_slice() {
+    [] => Err(GojoError::Failed(String::from("\x1b[31mfile not found:\x1b[0m\n\tno executable target found\n")).into()),
+    [only] => Ok(only),
+    _ => Err(
+      GojoError::Usage(format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tcould not determine which binary to run, use --bin <name>\n\tavailable binaries: {}\n",
+        names(Role::Binary)
+      ))
+      .into(),
+    ),
+  }
+}
+
+// The test binary to debug a test case in: the named one, otherwise the
+// only one there is.
+pub fn select_test<'a>(executables: &'a [Executable], bin: Option<&str>) -> Result<&'a Executable> {
+  let tests: Vec<&Executable> = executables.iter().filter(|e| e.role == Role::Test).collect();
+  let names = || -> String {
+    if tests.is_empty() {
+      return String::from("none");
+    }
+    tests.iter().map(|e| e.name.as_str()).collect::<Vec<&str>>().join(", ")
+  };
+  if let Some(bin) = bin {
+    return tests.iter().find(|e| e.name == bin).copied().ok_or_else(|| {
+      GojoError::Usage(format!("\x1b[31mincorrect usage:\x1b[0m\n\tno test binary named '{bin}'\n\tavailable test binaries: {}\n", names()))
+        .into()
+    });
+  }
+  match tests.as_slice() {
+    [] => Err(GojoError::Failed(String::from("\x1b[31mfile not found:\x1b[0m\n\tno test target found\n")).into()),
+    [only] => Ok(only),
+    _ => Err(
+      GojoError::Usage(format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tcould not determine which test binary to debug, use --bin <name>\n\tavailable test binaries: {}\n",
+        names()
+      ))
+      .into(),
+    ),
+  }
+}
@@ -0,0 +1,182 @@
+use crate::hash;
+use crate::toolchain;
+
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+use std::vec::Vec;
+
+const FINGERPRINT_FILE: &str = ".gojo-configure";
+
+// Environment variables CMake reads while configuring.
+const ENV_VARS: &[&str] = &[
+  "CC",
+  "CXX",
+  "CFLAGS",
+  "CXXFLAGS",
+  "LDFLAGS",
+  "CMAKE_GENERATOR",
+  "CMAKE_PREFIX_PATH",
+  "CMAKE_TOOLCHAIN_FILE",
+  "PKG_CONFIG_PATH",
+];
+
+// Everything that can change the result of `cmake -S . -B <build_dir>`,
+// recorded as `key: value` lines in the build directory.
+pub struct Fingerprint {
+  entries: Vec<(String, String)>,
+}
+
+impl Fingerprint {
+  fn get(&self, key: &str) -> Option<&str> {
+    self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+  }
+}
+
+pub fn compute(project_root: &str, build_dir: &str, args: &[String]) -> Fingerprint {
+  let mut entries: Vec<(String, String)> = Vec::new();
+  entries.push((String::from("flags"), args.join(" ")));
+  entries.push((String::from("cmake"), first_line_of(Command::new("cmake").arg("--version"))));
+  let cxx = toolchain::cxx_compiler();
+  entries.push((
+    String::from("compiler"),
+    format!("{} {}", cxx, first_line_of(Command::new(cxx.as_str()).arg("--version"))),
+  ));
+  for var in ENV_VARS {
+    entries.push((format!("env.{var}"), std::env::var(var).unwrap_or_default()));
+  }
+  // The generated hook lives in the build directory, but changes what a
+  // configure produces just like the project's own CMake files.
+  for arg in args {
+    if let Some(path) = arg.strip_prefix("-DCMAKE_PROJECT_INCLUDE=").filter(|path| !path.is_empty()) {
+      entries.push((String::from("hook"), hash::fnv1a_hex(&fs::read(path).unwrap_or_default())));
+    }
+  }
+
+  let mut files: Vec<String> = Vec::new();
+  collect_cmake_files(Path::new(project_root), build_dir, &mut files);
+  files.sort();
+  for file in files {
+    let contents = fs::read(file.as_str()).unwrap_or_default();
+    let relative = file.strip_prefix(project_root).unwrap_or(file.as_str()).trim_start_matches('/');
+    entries.push((format!("file.{relative}"), hash::fnv1a_hex(&contents)));
+  }
+  Fingerprint { entries }
+}
+
+// Explains why `build_dir` must be configured again. Empty when the last
+// successful configure used exactly the same inputs.
+pub fn changes(build_dir: &str, current: &Fingerprint) -> Vec<String> {
+  if !fs::exists(format!("{build_dir}/CMakeCache.txt")).unwrap_or(false) {
+    return vec![String::from("build directory has not been configured")];
+  }
+  let Some(previous) = load(build_dir) else {
+    return vec![String::from("no record of the previous configure")];
+  };
+
+  let mut reasons: Vec<String> = Vec::new();
+  for (key, value) in current.entries.iter() {
+    match previous.get(key) {
+      Some(old) if old == value => {}
+      Some(old) => reasons.push(describe_change(key, old, value)),
+      None if key.starts_with("file.") => reasons.push(format!("{} was added", &key[5..])),
+      None if key == "hook" => reasons.push(describe_change(key, "", value)),
+      None => reasons.push(format!("{key} changed")),
+    }
+  }
+  for (key, _) in previous.entries.iter() {
+    if key.starts_with("file.") && current.get(key).is_none() {
+      reasons.push(format!("{} was removed", &key[5..]));
+    }
+  }
+  reasons
+}
+
+pub fn save(build_dir: &str, fingerprint: &Fingerprint) -> Result<()> {
+  let mut contents = String::new();
+  for (key, value) in fingerprint.entries.iter() {
+    contents += format!("{key}: {value}\n").as_str();
+  }
+  fs::write(format!("{build_dir}/{FINGERPRINT_FILE}"), contents)
+}
+
+// Forgets the last configure so a failed run is never mistaken for an up to
+// date one.
+pub fn remove(build_dir: &str) {
+  let _ = fs::remove_file(format!("{build_dir}/{FINGERPRINT_FILE}"));
+}
+
+fn load(build_dir: &str) -> Option<Fingerprint> {
+  let contents = fs::read_to_string(format!("{build_dir}/{FINGERPRINT_FILE}")).ok()?;
+  let entries = contents
+    .lines()
+    .filter_map(|line| line.split_once(": "))
+    .map(|(key, value)| (key.to_string(), value.to_string()))
+    .collect();
+  Some(Fingerprint { entries })
+}
+
+fn describe_change(key: &str, old: &str, new: &str) -> String {
+  if let Some(file) = key.strip_prefix("file.") {
+    return format!("{file} changed");
+  }
+  if key == "hook" {
+    return String::from("settings applied from .gojo changed");
+  }
+  if key == "flags" {
+    let old_flags: Vec<&str> = old.split(' ').collect();
+    let changed: Vec<&str> = new.split(' ').filter(|flag| !old_flags.contains(flag)).collect();
+    return format!("configure flags changed ({})", changed.join(" "));
+  }
+  if let Some(var) = key.strip_prefix("env.") {
+    return format!("${var} changed ('{old}' -> '{new}')");
+  }
+  format!("{key} changed ('{old}' -> '{new}')")
+}
+
+fn first_line_of(command: &mut Command) -> String {
+  let output = command.stdin(Stdio::null()).stderr(Stdio::null()).output();
+  match output {
+    Ok(output) => String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("").to_string(),
+    Err(_) => String::new(),
+  }
+}
+
+// The CMakeLists.txt and *.cmake files of the project itself.
+pub fn collect_cmake_files(dir: &Path, build_dir: &str, files: &mut Vec<String>) {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    let name = entry.file_name().to_string_lossy().to_string();
+    if path.is_dir() {
+      if !is_ignored_dir(path.as_path(), build_dir) {
+        collect_cmake_files(path.as_path(), build_dir, files);
+      }
+    } else if name == "CMakeLists.txt" || name.ends_with(".cmake") {
+      files.push(path.to_string_lossy().to_string());
+    }
+  }
+}
+
+// Build trees, fetched dependencies and hidden directories, none of which
+// belong to the project.
+pub fn is_ignored_dir(path: &Path, build_dir: &str) -> bool {
+  let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+  name.starts_with('.')
+    || name == "_deps"
+    || name == "CMakeFiles"
+    || path.join("CMakeCache.txt").exists()
+    || path.join(FINGERPRINT_FILE).exists()
+    || is_same_dir(path, build_dir)
+}
+
+fn is_same_dir(path: &Path, build_dir: &str) -> bool {
+  match (fs::canonicalize(path), fs::canonicalize(build_dir)) {
+    (Ok(a), Ok(b)) => a == b,
+    _ => false,
+  }
+}
@@ -0,0 +1,116 @@
+use crate::error::GojoError;
+
+use std::io::Result;
+use std::process::Command;
+use std::process::Stdio;
+use std::vec::Vec;
+
+// A --gtest_filter matching what `ctest -R <pattern> -E <exclude>` would.
+// GoogleTest only has `*` and `?` globs, so the regular expressions are
+// limited to alternatives, `^`, `$`, `.` and `.*`. Everything else is
+// matched as plain text, anywhere in the name, the way `-R` does.
+pub fn filter(pattern: Option<&str>, exclude: Option<&str>) -> Result<Option<String>> {
+  if pattern.is_none() && exclude.is_none() {
+    return Ok(None);
+  }
+  let positive = match pattern {
+    Some(pattern) => globs(pattern)?,
+    None => String::from("*"),
+  };
+  match exclude {
+    Some(exclude) => Ok(Some(format!("{positive}-{}", globs(exclude)?))),
+    None => Ok(Some(positive)),
+  }
+}
+
+fn globs(regex: &str) -> Result<String> {
+  let mut globs: Vec<String> = Vec::new();
+  for alternative in regex.split('|') {
+    let anchored_start = alternative.starts_with('^');
+    let anchored_end = alternative.ends_with('$') && !alternative.ends_with("\\$");
+    let body = alternative.strip_prefix('^').unwrap_or(alternative);
+    let body = if anchored_end { &body[..body.len() - 1] } else { body };
+    if body.contains(['[', ']', '(', ')', '+', '?', '{', '}', '\\', ':']) {
+      return Err(
+        GojoError::Usage(format!(
+          "\x1b[31mincorrect usage:\x1b[0m\n\t'{regex}' can't be passed to GoogleTest\n\tuse plain names, '|', '^', '$', '.' and '.*'\n"
+        ))
+        .into(),
+      );
+    }
+    let body = body.replace(".*", "*").replace('.', "?");
+    let start = if anchored_start { "" } else { "*" };
+    let end = if anchored_end { "" } else { "*" };
+    globs.push(format!("{start}{body}{end}"));
+  }
+  Ok(globs.join(":"))
+}
+
+// The test cases the GoogleTest binary in `command` would run, from
+// --gtest_list_tests.
+pub fn list(command: &[String], cwd: &str, filter: Option<&str>) -> Vec<String> {
+  let Some((program, args)) = command.split_first() else {
+    return Vec::new();
+  };
+  let mut list = Command::new(program);
+  list.args(args).arg("--gtest_list_tests").current_dir(cwd).stdin(Stdio::null()).stderr(Stdio::null());
+  if let Some(filter) = filter {
+    list.arg(format!("--gtest_filter={filter}"));
+  }
+  let Ok(output) = list.output() else {
+    return Vec::new();
+  };
+  // Suites start at the beginning of a line and end with a dot, their cases
+  // follow indented. Typed and parameterized tests add a `#` comment.
+  let mut cases: Vec<String> = Vec::new();
+  let mut suite = String::new();
+  for line in String::from_utf8_lossy(&output.stdout).lines() {
+    let name = line.split('#').next().unwrap_or("").trim();
+    if name.is_empty() {
+      continue;
+    }
+    if !line.starts_with(' ') && name.ends_with('.') {
+      suite = name.to_string();
+    } else if line.starts_with(' ') && !suite.is_empty() {
+      cases.push(format!("{suite}{name}"));
+    }
+  }
+  cases
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::error;
+
+  #[test]
+  fn translates_alternatives_and_anchors() {
+    assert_eq!(globs("^A|B$").unwrap(), "A*:*B");
+    assert_eq!(globs("^HelloTest.Basic$").unwrap(), "HelloTest?Basic");
+    assert_eq!(globs("Basic").unwrap(), "*Basic*");
+  }
+
+  #[test]
+  fn translates_wildcards() {
+    assert_eq!(globs(".*").unwrap(), "***");
+    assert_eq!(globs("^Hello.*Test$").unwrap(), "Hello*Test");
+    assert_eq!(globs("^Sizes/Grows/.$").unwrap(), "Sizes/Grows/?");
+  }
+
+  #[test]
+  fn rejects_other_regex_syntax() {
+    for regex in ["HelloTest\\.Basic", "Hello(Test)", "a+", "[ab]", "a?", "a{2}", "Suite:Case"] {
+      let error = globs(regex).err().unwrap();
+      assert_eq!(error::exit_code(&error), error::EXIT_USAGE, "{regex}");
+    }
+  }
+
+  #[test]
+  fn combines_pattern_and_exclude() {
+    assert_eq!(filter(None, None).unwrap(), None);
+    assert_eq!(filter(Some("^Math"), None).unwrap().as_deref(), Some("Math*"));
+    assert_eq!(filter(None, Some("Slow")).unwrap().as_deref(), Some("*-*Slow*"));
+    assert_eq!(filter(Some("A|B"), Some("^C$")).unwrap().as_deref(), Some("*A*:*B*-C"));
+    assert!(filter(Some("A"), Some("B\\.C")).is_err());
+  }
+}
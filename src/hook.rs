@@ -0,0 +1,102 @@
+use crate::executables;
+
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+
+const HOOK_FILE: &str = "gojo-hook.cmake";
+
+// Writes CMake code that runs at the end of the project's top-level
+// CMakeLists.txt, once every target exists, and returns the file to pass as
+// CMAKE_PROJECT_INCLUDE. Settings from the config are applied this way so
+// the user's CMake files are never edited. Returns an empty path, which
+// clears a previous hook, when there is nothing to inject.
+pub fn write(build_dir: &str, code: &[String]) -> Result<String> {
+  let path = format!("{build_dir}/{HOOK_FILE}");
+  if code.is_empty() {
+    let _ = fs::remove_file(path.as_str());
+    return Ok(String::new());
+  }
+
+  let mut contents = String::from(
+    "# Generated by gojo from .gojo, regenerated on every build.
+# CMAKE_PROJECT_INCLUDE runs after every project() call, only the top-level
+# one is wanted.
+if(NOT CMAKE_SOURCE_DIR STREQUAL PROJECT_SOURCE_DIR OR DEFINED _gojo_hook_applied)
+  return()
+endif()
+set(_gojo_hook_applied TRUE)
+
+function(_gojo_apply)
+",
+  );
+  for line in code.iter().flat_map(|code| code.lines()) {
+    contents += format!("  {line}\n").as_str();
+  }
+  contents += "endfunction()\n\ncmake_language(DEFER DIRECTORY \"${CMAKE_SOURCE_DIR}\" CALL _gojo_apply)\n";
+  fs::write(path.as_str(), contents)?;
+  Ok(path)
+}
+
+// `pch: <target> <header>...` lines from the config as
+// target_precompile_headers() calls. Headers in angle brackets are system
+// headers, anything else is a path relative to the project root.
+pub fn precompile_headers(project_root: &str, lines: &[String]) -> Vec<String> {
+  let mut code: Vec<String> = Vec::new();
+  for line in lines {
+    let mut fields = line.split_whitespace();
+    let Some(target) = fields.next() else {
+      continue;
+    };
+    let headers: Vec<String> = fields
+      .map(|header| {
+        if header.starts_with('<') || header.starts_with('/') {
+          return header.to_string();
+        }
+        format!("\"{project_root}/{header}\"")
+      })
+      .collect();
+    if headers.is_empty() {
+      continue;
+    }
+    code.push(format!(
+      "if(TARGET {target})
+  target_precompile_headers({target} PRIVATE {})
+else()
+  message(WARNING \"gojo: precompiled headers are set for '{target}', which is not a target\")
+endif()",
+      headers.join(" ")
+    ));
+  }
+  code
+}
+
+// One executable per example in examples/, for projects whose CMake files
+// don't build them. Each is linked against the `lib` target when there is
+// one and lands in `<build_dir>/examples` under the example's name.
+pub fn examples(project_root: &str, cpp: &str) -> Vec<String> {
+  let mut code: Vec<String> = Vec::new();
+  if Path::new(format!("{project_root}/examples/CMakeLists.txt").as_str()).exists() {
+    return code;
+  }
+  for (name, source) in executables::find_examples(project_root, cpp) {
+    let target = format!("example_{name}");
+    let sources = if Path::new(format!("{project_root}/{source}").as_str()).is_dir() {
+      code.push(format!("file(GLOB_RECURSE _gojo_sources CONFIGURE_DEPENDS \"{project_root}/{source}/*.{cpp}\")"));
+      String::from("${_gojo_sources}")
+    } else {
+      format!("\"{project_root}/{source}\"")
+    };
+    code.push(format!(
+      "if(NOT TARGET {target})
+  add_executable({target} {sources})
+  set_target_properties({target} PROPERTIES OUTPUT_NAME {name} RUNTIME_OUTPUT_DIRECTORY \"${{CMAKE_BINARY_DIR}}/examples\")
+  target_include_directories({target} PRIVATE \"{project_root}/src\")
+  if(TARGET lib)
+    target_link_libraries({target} PRIVATE lib)
+  endif()
+endif()"
+    ));
+  }
+  code
+}
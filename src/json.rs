@@ -0,0 +1,309 @@
+use std::fmt;
+use std::vec::Vec;
+
+pub enum Value {
+  Null,
+  Bool(bool),
+  Number(f64),
+  String(String),
+  Array(Vec<Value>),
+  // Keys keep their insertion order so emitted objects read predictably.
+  Object(Vec<(String, Value)>),
+}
+
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Value::Null => write!(f, "null"),
+      Value::Bool(b) => write!(f, "{b}"),
+      Value::Number(n) => {
+        if !n.is_finite() {
+          write!(f, "null")
+        } else if n.fract() == 0.0 && n.abs() < 1e15 {
+          write!(f, "{}", *n as i64)
+        } else {
+          write!(f, "{n}")
+        }
+      }
+      Value::String(s) => write_string(f, s.as_str()),
+      Value::Array(items) => {
+        write!(f, "[")?;
+        for (idx, item) in items.iter().enumerate() {
+          if idx > 0 {
+            write!(f, ",")?;
+          }
+          write!(f, "{item}")?;
+        }
+        write!(f, "]")
+      }
+      Value::Object(fields) => {
+        write!(f, "{{")?;
+        for (idx, (key, value)) in fields.iter().enumerate() {
+          if idx > 0 {
+            write!(f, ",")?;
+          }
+          write_string(f, key.as_str())?;
+          write!(f, ":{value}")?;
+        }
+        write!(f, "}}")
+      }
+    }
+  }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+  write!(f, "\"")?;
+  for c in s.chars() {
+    match c {
+      '"' => write!(f, "\\\"")?,
+      '\\' => write!(f, "\\\\")?,
+      '\n' => write!(f, "\\n")?,
+      '\r' => write!(f, "\\r")?,
+      '\t' => write!(f, "\\t")?,
+      c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+      c => write!(f, "{c}")?,
+    }
+  }
+  write!(f, "\"")
+}
+
+impl From<bool> for Value {
+  fn from(b: bool) -> Value {
+    Value::Bool(b)
+  }
+}
+
+impl From<&str> for Value {
+  fn from(s: &str) -> Value {
+    Value::String(s.to_string())
+  }
+}
+
+impl From<String> for Value {
+  fn from(s: String) -> Value {
+    Value::String(s)
+  }
+}
+
+impl From<i32> for Value {
+  fn from(n: i32) -> Value {
+    Value::Number(n as f64)
+  }
+}
+
+impl From<u32> for Value {
+  fn from(n: u32) -> Value {
+    Value::Number(n as f64)
+  }
+}
+
+impl From<u64> for Value {
+  fn from(n: u64) -> Value {
+    Value::Number(n as f64)
+  }
+}
+
+impl From<usize> for Value {
+  fn from(n: usize) -> Value {
+    Value::Number(n as f64)
+  }
+}
+
+impl From<f64> for Value {
+  fn from(n: f64) -> Value {
+    Value::Number(n)
+  }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+  fn from(value: Option<T>) -> Value {
+    match value {
+      Some(v) => v.into(),
+      None => Value::Null,
+    }
+  }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+  fn from(items: Vec<T>) -> Value {
+    Value::Array(items.into_iter().map(|item| item.into()).collect())
+  }
+}
+
+impl Value {
+  pub fn get(&self, key: &str) -> Option<&Value> {
+    match self {
+      Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+      _ => None,
+    }
+  }
+
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Value::String(s) => Some(s.as_str()),
+      _ => None,
+    }
+  }
+
+  pub fn as_f64(&self) -> Option<f64> {
+    match self {
+      Value::Number(n) => Some(*n),
+      _ => None,
+    }
+  }
+
+  pub fn as_array(&self) -> Option<&Vec<Value>> {
+    match self {
+      Value::Array(items) => Some(items),
+      _ => None,
+    }
+  }
+}
+
+pub fn parse(text: &str) -> Option<Value> {
+  let mut parser = Parser {
+    bytes: text.as_bytes(),
+    pos: 0,
+  };
+  let value = parser.value()?;
+  parser.skip_whitespace();
+  if parser.pos != parser.bytes.len() {
+    return None;
+  }
+  Some(value)
+}
+
+struct Parser<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+}
+
+impl Parser<'_> {
+  fn skip_whitespace(&mut self) {
+    while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+      self.pos += 1;
+    }
+  }
+
+  fn peek(&self) -> Option<u8> {
+    self.bytes.get(self.pos).copied()
+  }
+
+  fn expect(&mut self, literal: &str) -> Option<()> {
+    if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+      self.pos += literal.len();
+      return Some(());
+    }
+    None
+  }
+
+  fn value(&mut self) -> Option<Value> {
+    self.skip_whitespace();
+    match self.peek()? {
+      b'n' => self.expect("null").map(|_| Value::Null),
+      b't' => self.expect("true").map(|_| Value::Bool(true)),
+      b'f' => self.expect("false").map(|_| Value::Bool(false)),
+      b'"' => self.string().map(Value::String),
+      b'[' => self.array(),
+      b'{' => self.object(),
+      _ => self.number(),
+    }
+  }
+
+  fn number(&mut self) -> Option<Value> {
+    let start = self.pos;
+    while let Some(c) = self.peek() {
+      if c.is_ascii_digit() || matches!(c, b'-' | b'+' | b'.' | b'e' | b'E') {
+        self.pos += 1;
+      } else {
+        break;
+      }
+    }
+    let text = std::str::from_utf8(&self.bytes[start..self.pos]).ok()?;
+    text.parse::<f64>().ok().map(Value::Number)
+  }
+
+  fn string(&mut self) -> Option<String> {
+    self.expect("\"")?;
+    let mut bytes: Vec<u8> = Vec::new();
+    loop {
+      let c = self.peek()?;
+      self.pos += 1;
+      match c {
+        b'"' => break,
+        b'\\' => {
+          let escaped = self.peek()?;
+          self.pos += 1;
+          match escaped {
+            b'n' => bytes.push(b'\n'),
+            b'r' => bytes.push(b'\r'),
+            b't' => bytes.push(b'\t'),
+            b'b' => bytes.push(0x08),
+            b'f' => bytes.push(0x0c),
+            b'u' => {
+              let mut code = self.hex4()?;
+              // Surrogate pairs encode characters outside the BMP.
+              if (0xd800..0xdc00).contains(&code) && self.expect("\\u").is_some() {
+                let low = self.hex4()?;
+                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+              }
+              let c = char::from_u32(code).unwrap_or('\u{fffd}');
+              let mut buf = [0; 4];
+              bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            other => bytes.push(other),
+          }
+        }
+        other => bytes.push(other),
+      }
+    }
+    String::from_utf8(bytes).ok()
+  }
+
+  fn hex4(&mut self) -> Option<u32> {
+    let digits = self.bytes.get(self.pos..self.pos + 4)?;
+    self.pos += 4;
+    u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+  }
+
+  fn array(&mut self) -> Option<Value> {
+    self.expect("[")?;
+    let mut items = Vec::new();
+    self.skip_whitespace();
+    if self.expect("]").is_some() {
+      return Some(Value::Array(items));
+    }
+    loop {
+      items.push(self.value()?);
+      self.skip_whitespace();
+      if self.expect(",").is_some() {
+        continue;
+      }
+      self.expect("]")?;
+      return Some(Value::Array(items));
+    }
+  }
+
+  fn object(&mut self) -> Option<Value> {
+    self.expect("{")?;
+    let mut fields = Vec::new();
+    self.skip_whitespace();
+    if self.expect("}").is_some() {
+      return Some(Value::Object(fields));
+    }
+    loop {
+      self.skip_whitespace();
+      let key = self.string()?;
+      self.skip_whitespace();
+      self.expect(":")?;
+      let value = self.value()?;
+      fields.push((key, value));
+      self.skip_whitespace();
+      if self.expect(",").is_some() {
+        continue;
+      }
+      self.expect("}")?;
+      return Some(Value::Object(fields));
+    }
+  }
+}
@@ -1,7 +1,39 @@
+#[macro_use]
+mod output;
+mod error;
 mod commands;
 mod plaintext;
 mod templates;
 mod packages;
+mod diagnostics;
+mod ctest;
+mod gtest;
+mod json;
+mod timings;
+mod toolchain;
+mod hash;
+mod fingerprint;
+#[cfg(target_os = "linux")]
+mod watch;
+mod sanitize;
+mod coverage;
+mod pgo;
+mod clangd;
+mod native;
+mod hook;
+mod package;
+mod deploy;
+mod cache;
+mod executables;
+mod debug;
+mod crash;
+mod xml;
+mod memcheck;
+mod profile;
+mod runconfig;
+mod testreport;
+
+use crate::error::GojoError;
 
 // TODO
 // * update command
@@ -14,7 +46,28 @@ mod packages;
 
 
 fn main() {
-  let args: std::vec::Vec<String> = std::env::args().collect();
+  let mut args: std::vec::Vec<String> = std::env::args().collect();
+
+  // --message-format is global, so it may appear anywhere on the command
+  // line before a `--`, what follows that belongs to the user's program.
+  let end = args.iter().position(|arg| arg == "--").unwrap_or(args.len());
+  if let Some(idx) = args[..end].iter().position(|arg| arg == "--message-format" || arg.starts_with("--message-format=")) {
+    let format = match args[idx].split_once('=') {
+      Some((_, format)) => Some(format.to_string()),
+      None if idx + 1 < args.len() => Some(args.remove(idx + 1)),
+      None => None,
+    };
+    args.remove(idx);
+    match format.as_deref() {
+      Some("json") => output::set_json(true),
+      Some("human") => output::set_json(false),
+      _ => {
+        eprintln!("\x1b[31mincorrect usage:\x1b[0m \n\t--message-format must be one of 'human' or 'json'");
+        std::process::exit(error::EXIT_USAGE);
+      }
+    }
+  }
+
   if args.len() == 1 {
     println!("{}", plaintext::WIN);
     commands::help();
@@ -39,8 +92,26 @@ fn main() {
     "run" => {
       result = commands::run(command_args);
     }
+    "debug" => {
+      result = commands::debug(command_args);
+    }
     "test" => {
-      result = commands::test();
+      result = commands::test(command_args);
+    }
+    "coverage" => {
+      result = commands::coverage(command_args);
+    }
+    "pgo" => {
+      result = commands::pgo(command_args);
+    }
+    "profile" => {
+      result = commands::profile(command_args);
+    }
+    "package" => {
+      result = commands::package(command_args);
+    }
+    "cache" => {
+      result = commands::cache(command_args);
     }
     "clean" => {
       result = commands::clean();
@@ -48,15 +119,21 @@ fn main() {
     "fmt" => {
       result = commands::fmt(command_args);
     }
-    "lint" => {
-      println!("\x1b[31mincorrect usage:\x1b[0m \n\tcommand not recognized: {command}");
-    }
     "check" => {
       result = commands::check();
     }
+    "watch" => {
+      result = commands::watch(command_args);
+    }
     "branch" => {
       result = commands::branch(command_args);
     }
+    "deploy" => {
+      result = commands::deploy(command_args);
+    }
+    "undeploy" => {
+      result = commands::undeploy(command_args);
+    }
     "install" => {
       result = commands::install(command_args);
     }
@@ -64,10 +141,29 @@ fn main() {
       commands::help();
     }
     _ => {
-      println!("\x1b[31mincorrect usage:\x1b[0m \n\tcommand not recognized: {command}");
+      result = Err(GojoError::Usage(format!("\x1b[31mincorrect usage:\x1b[0m \n\tcommand not recognized: {command}\n")).into());
+    }
+  }
+  let exit_code = match result.as_ref() {
+    Ok(()) => 0,
+    Err(err) => error::exit_code(err),
+  };
+  if output::is_json() {
+    if let Err(err) = result.as_ref() {
+      output::Event::new("error")
+        .with("message", output::strip_ansi(err.to_string().as_str()).trim())
+        .emit();
     }
+    output::Event::new("command-finished")
+      .with("command", command)
+      .with("success", result.is_ok())
+      .with("exit_code", exit_code)
+      .with("schema_version", output::SCHEMA_VERSION)
+      .emit();
+    std::process::exit(exit_code);
   }
-  if result.is_err() {
-    eprintln!("{}", result.unwrap_err())
+  if let Err(err) = result {
+    eprintln!("{err}");
   }
+  std::process::exit(exit_code);
 }
@@ -0,0 +1,281 @@
+use crate::error::GojoError;
+use crate::output::Event;
+use crate::toolchain;
+use crate::xml;
+
+use std::fs;
+use std::io::Error;
+use std::io::Result;
+use std::path::Path;
+use std::vec::Vec;
+
+pub fn check_installed() -> Result<String> {
+  match toolchain::find_program("valgrind") {
+    Some(valgrind) => Ok(valgrind.to_string_lossy().to_string()),
+    None => Err(GojoError::ToolMissing(String::from(
+      "\x1b[31mtool not found:\x1b[0m\n\t'valgrind' is not installed or not on your PATH\n",
+    ))
+    .into()),
+  }
+}
+
+// Every `*.supp` file in the project, outside hidden directories and the
+// build directory.
+pub fn suppressions(project_root: &str, build_dir: &str) -> Vec<String> {
+  let mut files: Vec<String> = Vec::new();
+  find_suppressions(Path::new(project_root), Path::new(build_dir), &mut files);
+  files.sort();
+  files
+}
+
+fn find_suppressions(dir: &Path, build_dir: &Path, files: &mut Vec<String>) {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    let name = entry.file_name().to_string_lossy().to_string();
+    if path.is_dir() {
+      if !name.starts_with('.') && path != build_dir {
+        find_suppressions(path.as_path(), build_dir, files);
+      }
+    } else if name.ends_with(".supp") {
+      files.push(path.to_string_lossy().to_string());
+    }
+  }
+}
+
+// Valgrind options for a leak check that writes its findings as XML to
+// `xml_file`, where `%p` stands for the process id so every process a test
+// starts gets its own file.
+pub fn options(xml_file: &str, suppressions: &[String]) -> Vec<String> {
+  let mut options: Vec<String> = [
+    "--tool=memcheck",
+    "--leak-check=full",
+    "--show-leak-kinds=definite,possible",
+    "--errors-for-leak-kinds=definite,possible",
+    "--track-origins=yes",
+    "--num-callers=30",
+    "--xml=yes",
+  ]
+  .iter()
+  .map(|option| option.to_string())
+  .collect();
+  options.push(format!("--xml-file={xml_file}"));
+  for file in suppressions {
+    options.push(format!("--suppressions={file}"));
+  }
+  options
+}
+
+// `options` as the value of ctest's MemoryCheckCommandOptions. ctest splits
+// it on spaces but keeps an argument wrapped in double quotes whole, so
+// every option is quoted. A double quote inside one can't be passed.
+pub fn ctest_options(options: &[String]) -> Result<String> {
+  let mut quoted: Vec<String> = Vec::new();
+  for option in options {
+    if option.contains('"') {
+      return Err(GojoError::Config(format!(
+        "\x1b[31minvalid path:\x1b[0m\n\tctest can't pass '{option}' on to Valgrind\n\trename the file or directory so it has no '\"' in it\n"
+      ))
+      .into());
+    }
+    quoted.push(format!("\"{option}\""));
+  }
+  Ok(quoted.join(" "))
+}
+
+pub struct Frame {
+  pub function: String,
+  // The source file, absolute when Valgrind knows its directory.
+  pub file: Option<String>,
+  pub line: Option<u32>,
+}
+
+// One error or leak record from Valgrind.
+pub struct Issue {
+  pub kind: String,
+  pub message: String,
+  pub leaked_bytes: u64,
+  pub stack: Vec<Frame>,
+}
+
+impl Issue {
+  pub fn is_leak(&self) -> bool {
+    self.kind.starts_with("Leak_")
+  }
+
+  // Where it happened: the innermost frame in the project's own sources,
+  // otherwise the innermost frame with a source file.
+  fn location(&self, project_root: &str) -> Option<&Frame> {
+    let with_file = || self.stack.iter().filter(|frame| frame.file.is_some());
+    with_file()
+      .find(|frame| frame.file.as_deref().is_some_and(|file| file.starts_with(project_root)))
+      .or_else(|| with_file().next())
+      .or_else(|| self.stack.first())
+  }
+}
+
+// The issues in every XML file Valgrind wrote to `dir`. A file that isn't
+// complete XML fails the check, since Valgrind only finishes it when the
+// process it was watching exits normally.
+pub fn read_reports(dir: &str) -> Result<Vec<Issue>> {
+  let mut files: Vec<String> = fs::read_dir(dir)
+    .map(|entries| {
+      entries
+        .flatten()
+        .map(|entry| entry.path().to_string_lossy().to_string())
+        .filter(|path| path.ends_with(".xml"))
+        .collect()
+    })
+    .unwrap_or_default();
+  files.sort();
+
+  let mut issues: Vec<Issue> = Vec::new();
+  for file in files {
+    let Some(root) = fs::read_to_string(file.as_str()).ok().and_then(|text| xml::parse(text.as_str())) else {
+      return Err(GojoError::TestFailed(format!(
+        "\x1b[31mmemcheck report unreadable:\x1b[0m\n\t'{file}' is not complete Valgrind XML\n\tthe process it was checking was probably killed or timed out\n"
+      ))
+      .into());
+    };
+    for error in root.children("error") {
+      let xwhat = error.child("xwhat");
+      let message = xwhat
+        .and_then(|xwhat| xwhat.child_text("text"))
+        .or_else(|| error.child_text("what"))
+        .unwrap_or_default()
+        .to_string();
+      let leaked_bytes = xwhat.and_then(|xwhat| xwhat.child_text("leakedbytes")).and_then(|bytes| bytes.parse().ok()).unwrap_or(0);
+      let stack = error
+        .child("stack")
+        .map(|stack| {
+          stack
+            .children("frame")
+            .map(|frame| Frame {
+              function: frame.child_text("fn").or_else(|| frame.child_text("obj")).unwrap_or("???").to_string(),
+              file: frame.child_text("file").map(|file| match frame.child_text("dir") {
+                Some(dir) => format!("{dir}/{file}"),
+                None => file.to_string(),
+              }),
+              line: frame.child_text("line").and_then(|line| line.parse().ok()),
+            })
+            .collect()
+        })
+        .unwrap_or_default();
+      issues.push(Issue { kind: error.child_text("kind").unwrap_or_default().to_string(), message, leaked_bytes, stack });
+    }
+  }
+  Ok(issues)
+}
+
+// Prints one line per error and leak with where it happened, and fails
+// when there are any.
+pub fn report(issues: &[Issue], project_root: &str) -> Result<()> {
+  let leaks = issues.iter().filter(|issue| issue.is_leak()).count();
+  let errors = issues.len() - leaks;
+  let leaked_bytes: u64 = issues.iter().map(|issue| issue.leaked_bytes).sum();
+
+  if !issues.is_empty() {
+    status!("\n\x1b[1;35mMemcheck:\x1b[0m\n");
+  }
+  for issue in issues {
+    let location = issue.location(project_root);
+    let file = location.and_then(|frame| frame.file.as_deref()).map(|file| {
+      file.strip_prefix(project_root).map(|file| file.trim_start_matches('/')).unwrap_or(file).to_string()
+    });
+    let at = match (location, file.as_deref()) {
+      (Some(frame), Some(file)) => format!("{} ({}:{})", frame.function, file, frame.line.unwrap_or(0)),
+      (Some(frame), None) => frame.function.clone(),
+      _ => String::from("unknown location"),
+    };
+    let label = if issue.is_leak() { "\x1b[1;33mleak\x1b[0m " } else { "\x1b[1;31merror\x1b[0m" };
+    status!("  {} {}\n        at {}\n", label, issue.message, at);
+
+    Event::new("memcheck-issue")
+      .with("kind", issue.kind.as_str())
+      .with("message", issue.message.as_str())
+      .with("leaked_bytes", issue.leaked_bytes)
+      .with("function", location.map(|frame| frame.function.as_str()))
+      .with("file", file)
+      .with("line", location.and_then(|frame| frame.line))
+      .emit();
+  }
+  Event::new("memcheck-finished")
+    .with("errors", errors)
+    .with("leaks", leaks)
+    .with("leaked_bytes", leaked_bytes)
+    .emit();
+
+  if issues.is_empty() {
+    status!("\x1b[1;32mMemcheck found no errors or leaks\x1b[0m\n\n");
+    return Ok(());
+  }
+  Err(failed(errors, leaks, leaked_bytes))
+}
+
+fn failed(errors: usize, leaks: usize, leaked_bytes: u64) -> Error {
+  GojoError::TestFailed(format!(
+    "\x1b[31mmemcheck found {errors} error(s) and {leaks} leak(s):\x1b[0m\n\t{leaked_bytes} bytes leaked\n"
+  ))
+  .into()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const VALGRIND: &str = include_str!("../tests/data/valgrind-memcheck.xml");
+
+  fn report_dir(name: &str, files: &[(&str, &str)]) -> String {
+    let dir = std::env::temp_dir().join(format!("gojo-memcheck-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (file, text) in files {
+      fs::write(dir.join(file), text).unwrap();
+    }
+    dir.to_string_lossy().to_string()
+  }
+
+  #[test]
+  fn reads_errors_and_leaks() {
+    let dir = report_dir("read", &[("test.41235.xml", VALGRIND), ("notes.txt", "not xml")]);
+    let issues = read_reports(dir.as_str()).unwrap();
+    let _ = fs::remove_dir_all(dir.as_str());
+
+    assert_eq!(issues.len(), 2);
+    assert_eq!(issues[0].kind, "InvalidRead");
+    assert_eq!(issues[0].message, "Invalid read of size 4");
+    assert!(!issues[0].is_leak());
+    let frame = issues[0].location("/home/dev/demo").unwrap();
+    assert_eq!(frame.function, "Buffer<int>::at(unsigned long) const");
+    assert_eq!(frame.file.as_deref(), Some("/home/dev/demo/src/buffer.h"));
+    assert_eq!(frame.line, Some(27));
+
+    assert!(issues[1].is_leak());
+    assert_eq!(issues[1].leaked_bytes, 40);
+    assert_eq!(issues[1].message, "40 bytes in 1 blocks are definitely lost in loss record 1 of 1");
+    // Skips the allocator frame for the project's own code.
+    assert_eq!(issues[1].location("/home/dev/demo").unwrap().function, "make_table()");
+  }
+
+  #[test]
+  fn fails_on_truncated_report() {
+    let cut = VALGRIND.find("<status>\n  <state>FINISHED").unwrap();
+    let dir = report_dir("truncated", &[("test.1.xml", VALGRIND), ("test.2.xml", &VALGRIND[..cut])]);
+    let result = read_reports(dir.as_str());
+    let _ = fs::remove_dir_all(dir.as_str());
+
+    let error = result.err().unwrap();
+    assert!(error.to_string().contains("test.2.xml"));
+  }
+
+  #[test]
+  fn quotes_ctest_options() {
+    let options = options("/tmp/my project/memcheck/test.%p.xml", &[String::from("/tmp/my project/app.supp")]);
+    let quoted = ctest_options(&options).unwrap();
+    assert!(quoted.starts_with("\"--tool=memcheck\" "));
+    assert!(quoted.ends_with(" \"--xml-file=/tmp/my project/memcheck/test.%p.xml\" \"--suppressions=/tmp/my project/app.supp\""));
+    assert!(ctest_options(&[String::from("--suppressions=/tmp/a\"b.supp")]).is_err());
+  }
+}
@@ -0,0 +1,544 @@
+use crate::cache;
+use crate::json::Value;
+use crate::toolchain;
+use crate::error::GojoError;
+use crate::executables;
+use crate::executables::Executable;
+use crate::executables::Role;
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io::Error;
+use std::io::Result;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::thread;
+use std::time::SystemTime;
+use std::vec::Vec;
+
+extern crate num_cpus;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+  Library,
+  Executable,
+  Example,
+  Test,
+}
+
+// One `target:` line from the config:
+//
+//   target: <lib|exe|example|test> <name> <source>... [link=<name>,...] [include=<dir>,...]
+//
+// Sources are files or directories searched recursively, relative to the
+// project root. Links name library targets, library files or system
+// libraries.
+pub struct Target {
+  pub kind: Kind,
+  pub name: String,
+  pub sources: Vec<String>,
+  pub links: Vec<String>,
+  pub includes: Vec<String>,
+}
+
+impl Target {
+  pub fn parse(line: &str) -> Result<Target> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 3 {
+      return Err(invalid_target(line, "expected '<lib|exe|example|test> <name> <source>...'"));
+    }
+    let kind = match fields[0] {
+      "lib" => Kind::Library,
+      "exe" => Kind::Executable,
+      "example" => Kind::Example,
+      "test" => Kind::Test,
+      _ => return Err(invalid_target(line, "the kind must be one of lib, exe, example or test")),
+    };
+
+    let mut target = Target {
+      kind,
+      name: fields[1].to_string(),
+      sources: Vec::new(),
+      links: Vec::new(),
+      includes: Vec::new(),
+    };
+    for field in &fields[2..] {
+      if let Some(links) = field.strip_prefix("link=") {
+        target.links.extend(links.split(',').filter(|l| !l.is_empty()).map(String::from));
+      } else if let Some(includes) = field.strip_prefix("include=") {
+        target.includes.extend(includes.split(',').filter(|i| !i.is_empty()).map(String::from));
+      } else {
+        target.sources.push(field.to_string());
+      }
+    }
+    if target.sources.is_empty() {
+      return Err(invalid_target(line, "no sources listed"));
+    }
+    Ok(target)
+  }
+}
+
+fn invalid_target(line: &str, reason: &str) -> Error {
+  GojoError::Config(
+    format!("\x1b[31minvalid config:\x1b[0m\n\ttarget '{line}': {reason}\n"),
+  ).into()
+}
+
+// Targets for the layout `gojo init` creates, used when the config lists
+// none: a `lib` library from src/lib, the executable from the files directly
+// in src, one executable per example in examples, and a GoogleTest binary
+// from test.
+pub fn infer_targets(project_root: &str, name: &str, cpp: &str) -> Vec<Target> {
+  let mut targets: Vec<Target> = Vec::new();
+  let has_lib = fs::exists(format!("{project_root}/src/lib")).unwrap_or(false);
+  if has_lib {
+    targets.push(Target {
+      kind: Kind::Library,
+      name: String::from("lib"),
+      sources: vec![String::from("src/lib")],
+      links: Vec::new(),
+      includes: Vec::new(),
+    });
+  }
+  let lib_links = if has_lib { vec![String::from("lib")] } else { Vec::new() };
+
+  let mut main_sources: Vec<String> = fs::read_dir(format!("{project_root}/src"))
+    .map(|entries| {
+      entries
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|file| file.ends_with(format!(".{cpp}").as_str()))
+        .map(|file| format!("src/{file}"))
+        .collect()
+    })
+    .unwrap_or_default();
+  main_sources.sort();
+  if !main_sources.is_empty() {
+    targets.push(Target {
+      kind: Kind::Executable,
+      name: name.to_string(),
+      sources: main_sources,
+      links: lib_links.clone(),
+      includes: Vec::new(),
+    });
+  }
+
+  for (example, source) in executables::find_examples(project_root, cpp) {
+    targets.push(Target {
+      kind: Kind::Example,
+      name: example,
+      sources: vec![source],
+      links: lib_links.clone(),
+      includes: vec![String::from("src")],
+    });
+  }
+
+  if fs::exists(format!("{project_root}/test")).unwrap_or(false) {
+    let mut links = lib_links;
+    links.extend([
+      String::from("~/.gojo/lib/gtest/libgtest_main.a"),
+      String::from("~/.gojo/lib/gtest/libgtest.a"),
+      String::from("pthread"),
+    ]);
+    targets.push(Target {
+      kind: Kind::Test,
+      name: format!("{name}_test"),
+      sources: vec![String::from("test")],
+      links,
+      includes: Vec::new(),
+    });
+  }
+  targets
+}
+
+// Flags for the build mode, matching CMake's defaults for Debug and Release.
+pub fn mode_flags(release: bool, lto: bool) -> Vec<String> {
+  let mut flags: Vec<String> = if release {
+    vec![String::from("-O3"), String::from("-DNDEBUG")]
+  } else {
+    vec![String::from("-g")]
+  };
+  if lto {
+    flags.push(String::from("-flto"));
+  }
+  flags
+}
+
+pub struct Build {
+  pub project_root: String,
+  pub build_dir: String,
+  pub std: String,
+  pub cpp: String,
+  // Passed to every compile and link, as CMAKE_CXX_FLAGS is.
+  pub compile_flags: Vec<String>,
+  pub link_flags: Vec<String>,
+  pub tests: bool,
+  // Compile through the shared object cache.
+  pub cache: bool,
+}
+
+struct Job {
+  source: String,
+  object: String,
+  command: Vec<String>,
+}
+
+// Compiles and links `targets`, skipping objects whose sources, headers and
+// command line are unchanged. Progress is reported through `echo` in the
+// same form as CMake's, and compiler and linker output is returned for the
+// diagnostics summary.
+pub fn build(build: &Build, targets: &[Target], echo: &(dyn Fn(&str) + Sync)) -> Result<(bool, Vec<String>)> {
+  let cxx = toolchain::cxx_compiler();
+  let targets: Vec<&Target> = targets.iter().filter(|t| build.tests || t.kind != Kind::Test).collect();
+
+  let mut jobs: Vec<Job> = Vec::new();
+  let mut objects: Vec<Vec<String>> = Vec::new();
+  for target in targets.iter() {
+    let mut target_objects: Vec<String> = Vec::new();
+    for source in collect_sources(build, target) {
+      let relative = source.strip_prefix(build.project_root.as_str()).unwrap_or(source.as_str()).trim_start_matches('/');
+      let object = format!("{}/obj/{}/{}.o", build.build_dir, target.name, relative);
+
+      let mut command = vec![cxx.clone(), format!("-std=c++{}", build.std)];
+      // The warnings the CMake template turns on.
+      command.extend(["-Wall", "-Wextra", "-Werror"].map(String::from));
+      command.extend(build.compile_flags.iter().cloned());
+      for include in target.includes.iter() {
+        command.push(format!("-I{}", resolve(build.project_root.as_str(), include)));
+      }
+      if let Some(home) = env::home_dir() {
+        command.push(format!("-I{}/.gojo/include", home.to_string_lossy()));
+      }
+      command.extend([
+        String::from("-MMD"),
+        String::from("-MF"),
+        format!("{object}.d"),
+        String::from("-c"),
+        source.clone(),
+        String::from("-o"),
+        object.clone(),
+      ]);
+      target_objects.push(object.clone());
+      jobs.push(Job { source, object, command });
+    }
+    objects.push(target_objects);
+  }
+  write_compile_commands(build, &jobs)?;
+
+  let stale: Vec<&Job> = jobs.iter().filter(|job| !is_up_to_date(job)).collect();
+  let mut output: Vec<String> = Vec::new();
+  if !compile(stale, build, echo, &mut output)? {
+    return Ok((false, output));
+  }
+
+  // Libraries are linked first so executables can depend on them.
+  let mut order: Vec<usize> = (0..targets.len()).collect();
+  order.sort_by_key(|i| targets[*i].kind != Kind::Library);
+  for i in order {
+    let target = targets[i];
+    let artifact = artifact_path(build.build_dir.as_str(), target);
+    let command = if target.kind == Kind::Library {
+      let mut command = vec![String::from("ar"), String::from("rcs"), artifact.clone()];
+      command.extend(objects[i].iter().cloned());
+      command
+    } else {
+      let mut command = vec![cxx.clone()];
+      command.extend(build.compile_flags.iter().cloned());
+      command.extend(build.link_flags.iter().cloned());
+      command.extend(objects[i].iter().cloned());
+      for link in target.links.iter() {
+        command.push(link_argument(build, &targets, link));
+      }
+      command.extend([String::from("-o"), artifact.clone()]);
+      command
+    };
+
+    let inputs: Vec<String> = command.iter().filter(|arg| arg.ends_with(".o") || arg.ends_with(".a")).cloned().collect();
+    if !is_link_up_to_date(artifact.as_str(), &command, &inputs) {
+      let kind = match target.kind {
+        Kind::Library => "static library",
+        _ => "executable",
+      };
+      echo(format!("[100%] Linking CXX {kind} {}", target.name).as_str());
+      // An archive is rebuilt from scratch so removed sources leave it too.
+      let _ = fs::remove_file(artifact.as_str());
+      if let Some(dir) = Path::new(artifact.as_str()).parent() {
+        fs::create_dir_all(dir)?;
+      }
+      let result = Command::new(&command[0]).args(&command[1..]).output().map_err(|e| toolchain::spawn_error(command[0].as_str(), e))?;
+      for line in String::from_utf8_lossy(&result.stderr).lines().chain(String::from_utf8_lossy(&result.stdout).lines()) {
+        echo(line);
+        output.push(line.to_string());
+      }
+      if !result.status.success() {
+        return Ok((false, output));
+      }
+      fs::write(format!("{artifact}.cmd"), command.join("\n"))?;
+    }
+    echo(format!("[100%] Built target {}", target.name).as_str());
+  }
+
+  if build.tests {
+    write_ctest_file(build, &targets)?;
+  }
+  Ok((true, output))
+}
+
+// Runs the jobs on one thread per core. After the first failure no new jobs
+// are started, but the ones in flight finish so all their errors are shown.
+fn compile(jobs: Vec<&Job>, build: &Build, echo: &(dyn Fn(&str) + Sync), output: &mut Vec<String>) -> Result<bool> {
+  let total = jobs.len();
+  let queue = Mutex::new(jobs.into_iter().collect::<VecDeque<&Job>>());
+  let started = AtomicUsize::new(0);
+  let failed = AtomicBool::new(false);
+  let lines = Mutex::new(Vec::new());
+  let spawn_error: Mutex<Option<Error>> = Mutex::new(None);
+
+  thread::scope(|scope| {
+    for _ in 0..num_cpus::get().min(total.max(1)) {
+      scope.spawn(|| loop {
+        if failed.load(Ordering::SeqCst) {
+          break;
+        }
+        let Some(job) = queue.lock().unwrap().pop_front() else {
+          break;
+        };
+        let n = started.fetch_add(1, Ordering::SeqCst) + 1;
+        let object = job.object.strip_prefix(build.build_dir.as_str()).unwrap_or(job.object.as_str()).trim_start_matches('/');
+        echo(format!("[{:>3}%] Building CXX object {}", n * 100 / total, object).as_str());
+
+        if let Some(dir) = Path::new(job.object.as_str()).parent() {
+          let _ = fs::create_dir_all(dir);
+        }
+        let result = if build.cache {
+          cache::compile(build.project_root.as_str(), &job.command)
+        } else {
+          Command::new(&job.command[0]).args(&job.command[1..]).output()
+        };
+        let result = match result {
+          Ok(result) => result,
+          Err(e) => {
+            *spawn_error.lock().unwrap() = Some(toolchain::spawn_error(job.command[0].as_str(), e));
+            failed.store(true, Ordering::SeqCst);
+            break;
+          }
+        };
+
+        let text = String::from_utf8_lossy(&result.stderr).to_string() + String::from_utf8_lossy(&result.stdout).as_ref();
+        let mut lines = lines.lock().unwrap();
+        for line in text.lines() {
+          echo(line);
+          lines.push(line.to_string());
+        }
+        if result.status.success() {
+          let _ = fs::write(format!("{}.cmd", job.object), job.command.join("\n"));
+        } else {
+          // A failed compile may leave a stale object behind.
+          let _ = fs::remove_file(job.object.as_str());
+          failed.store(true, Ordering::SeqCst);
+        }
+      });
+    }
+  });
+
+  if let Some(error) = spawn_error.into_inner().unwrap() {
+    return Err(error);
+  }
+  output.extend(lines.into_inner().unwrap());
+  Ok(!failed.load(Ordering::SeqCst))
+}
+
+fn collect_sources(build: &Build, target: &Target) -> Vec<String> {
+  let mut sources: Vec<String> = Vec::new();
+  for source in target.sources.iter() {
+    let path = resolve(build.project_root.as_str(), source);
+    if Path::new(path.as_str()).is_dir() {
+      collect_dir(Path::new(path.as_str()), build.cpp.as_str(), &mut sources);
+    } else {
+      sources.push(path);
+    }
+  }
+  sources.sort();
+  sources.dedup();
+  sources
+}
+
+fn collect_dir(dir: &Path, cpp: &str, sources: &mut Vec<String>) {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if path.is_dir() {
+      collect_dir(path.as_path(), cpp, sources);
+    } else if entry.file_name().to_string_lossy().ends_with(format!(".{cpp}").as_str()) {
+      sources.push(path.to_string_lossy().to_string());
+    }
+  }
+}
+
+fn artifact_path(build_dir: &str, target: &Target) -> String {
+  match target.kind {
+    Kind::Library => format!("{build_dir}/lib{}.a", target.name),
+    Kind::Example => format!("{build_dir}/examples/{}", target.name),
+    _ => format!("{build_dir}/{}", target.name),
+  }
+}
+
+// What `gojo run` can launch from these targets.
+pub fn executables(build_dir: &str, targets: &[Target]) -> Vec<Executable> {
+  let mut executables: Vec<Executable> = Vec::new();
+  for target in targets {
+    let role = match target.kind {
+      Kind::Library => continue,
+      Kind::Executable => Role::Binary,
+      Kind::Example => Role::Example,
+      Kind::Test => Role::Test,
+    };
+    executables.push(Executable {
+      name: target.name.clone(),
+      target: target.name.clone(),
+      path: artifact_path(build_dir, target),
+      role,
+    });
+  }
+  executables
+}
+
+// Library targets link by name, anything that looks like a path is passed
+// as a file and the rest are system libraries.
+fn link_argument(build: &Build, targets: &[&Target], link: &str) -> String {
+  if let Some(library) = targets.iter().find(|t| t.kind == Kind::Library && t.name == link) {
+    return artifact_path(build.build_dir.as_str(), library);
+  }
+  if link.contains('/') || link.ends_with(".a") || link.ends_with(".so") {
+    return resolve(build.project_root.as_str(), link);
+  }
+  format!("-l{link}")
+}
+
+fn resolve(project_root: &str, path: &str) -> String {
+  if let Some(rest) = path.strip_prefix("~/") {
+    if let Some(home) = env::home_dir() {
+      return format!("{}/{rest}", home.to_string_lossy());
+    }
+  }
+  if path.starts_with('/') {
+    return path.to_string();
+  }
+  format!("{project_root}/{path}")
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+  fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// An object is current when it is newer than its source and every header
+// the last compile read, and was built with the same command line.
+fn is_up_to_date(job: &Job) -> bool {
+  let Some(built) = modified(job.object.as_str()) else {
+    return false;
+  };
+  if fs::read_to_string(format!("{}.cmd", job.object)).ok() != Some(job.command.join("\n")) {
+    return false;
+  }
+  let Ok(depfile) = fs::read_to_string(format!("{}.d", job.object)) else {
+    return false;
+  };
+  let dependencies = parse_depfile(depfile.as_str());
+  if !dependencies.contains(&job.source) {
+    return false;
+  }
+  dependencies.iter().all(|dep| modified(dep.as_str()).is_some_and(|time| time <= built))
+}
+
+fn is_link_up_to_date(artifact: &str, command: &[String], inputs: &[String]) -> bool {
+  let Some(built) = modified(artifact) else {
+    return false;
+  };
+  if fs::read_to_string(format!("{artifact}.cmd")).ok() != Some(command.join("\n")) {
+    return false;
+  }
+  inputs.iter().all(|input| modified(input.as_str()).is_some_and(|time| time <= built))
+}
+
+// Reads the prerequisites from a Makefile rule written by -MMD. Lines are
+// continued with a trailing backslash and spaces in paths are escaped.
+fn parse_depfile(contents: &str) -> Vec<String> {
+  let joined = contents.replace("\\\n", " ").replace("\\\r\n", " ");
+  let Some((_, prerequisites)) = joined.split_once(": ") else {
+    return Vec::new();
+  };
+
+  let mut paths: Vec<String> = Vec::new();
+  let mut current = String::new();
+  let mut chars = prerequisites.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' if chars.peek() == Some(&' ') => {
+        current.push(' ');
+        chars.next();
+      }
+      ' ' | '\t' | '\n' | '\r' => {
+        if !current.is_empty() {
+          paths.push(std::mem::take(&mut current));
+        }
+      }
+      _ => current.push(c),
+    }
+  }
+  if !current.is_empty() {
+    paths.push(current);
+  }
+  paths
+}
+
+fn write_compile_commands(build: &Build, jobs: &[Job]) -> Result<()> {
+  let entries: Vec<Value> = jobs
+    .iter()
+    .map(|job| {
+      Value::Object(vec![
+        (String::from("directory"), Value::from(build.project_root.as_str())),
+        (String::from("file"), Value::from(job.source.as_str())),
+        (String::from("output"), Value::from(job.object.as_str())),
+        (String::from("arguments"), Value::from(job.command.clone())),
+      ])
+    })
+    .collect();
+  fs::create_dir_all(build.build_dir.as_str())?;
+  let contents = Value::Array(entries).to_string();
+  let path = format!("{}/compile_commands.json", build.build_dir);
+  // Rewriting an unchanged database would make clangd reindex everything.
+  if fs::read_to_string(path.as_str()).ok().as_deref() == Some(contents.as_str()) {
+    return Ok(());
+  }
+  fs::write(path, contents)
+}
+
+// A CTestTestfile.cmake is all ctest needs, so `gojo test` works the same
+// with either backend.
+fn write_ctest_file(build: &Build, targets: &[&Target]) -> Result<()> {
+  let mut contents = String::from("# Generated by gojo for the native build backend.\n");
+  for target in targets.iter().filter(|t| t.kind == Kind::Test) {
+    contents += format!(
+      "add_test([=[{0}]=] \"{1}\")\nset_tests_properties([=[{0}]=] PROPERTIES WORKING_DIRECTORY \"{2}\")\n",
+      target.name,
+      artifact_path(build.build_dir.as_str(), target),
+      build.build_dir
+    )
+    .as_str();
+  }
+  let path = format!("{}/CTestTestfile.cmake", build.build_dir);
+  // Like the compilation database, only rewritten when it changes, so
+  // `gojo watch` and ctest don't see a new file on every build.
+  if fs::read_to_string(path.as_str()).ok().as_deref() == Some(contents.as_str()) {
+    return Ok(());
+  }
+  fs::write(path, contents)
+}
@@ -0,0 +1,142 @@
+use crate::json::Value;
+
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Result;
+use std::io::Write;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::vec::Vec;
+
+// Version of the event schema documented in the README. Bump it whenever an
+// event or field is removed or changes meaning.
+pub const SCHEMA_VERSION: u32 = 1;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+// Human readable output. Suppressed entirely in `--message-format json` mode
+// so stdout only ever carries newline-delimited events.
+macro_rules! status {
+  ($($arg:tt)*) => {
+    if !$crate::output::is_json() {
+      print!($($arg)*);
+    }
+  };
+}
+
+macro_rules! statusln {
+  ($($arg:tt)*) => {
+    if !$crate::output::is_json() {
+      println!($($arg)*);
+    }
+  };
+}
+
+pub fn set_json(enabled: bool) {
+  JSON.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+  JSON.load(Ordering::Relaxed)
+}
+
+// Where a child process' stdout should go. In JSON mode it is redirected to
+// stderr so it can't interleave with events.
+pub fn child_stdout() -> Stdio {
+  if is_json() {
+    return Stdio::from(std::io::stderr());
+  }
+  Stdio::inherit()
+}
+
+pub struct Event {
+  fields: Vec<(String, Value)>,
+}
+
+impl Event {
+  pub fn new(reason: &str) -> Event {
+    Event {
+      fields: vec![(String::from("reason"), Value::from(reason))],
+    }
+  }
+
+  pub fn with<V: Into<Value>>(mut self, key: &str, value: V) -> Event {
+    self.fields.push((key.to_string(), value.into()));
+    self
+  }
+
+  // Writes the event as a single line of JSON. A no-op in human mode.
+  pub fn emit(self) {
+    if !is_json() {
+      return;
+    }
+    let line = Value::Object(self.fields).to_string();
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{line}");
+    let _ = stdout.flush();
+  }
+}
+
+// Runs `command` with stdout and stderr captured, interleaved in arrival
+// order. Each line is handed to `echo` as it arrives so callers can decide
+// what to stream to the terminal.
+pub fn capture<F>(command: &mut Command, echo: F) -> Result<(ExitStatus, Vec<String>)>
+where
+  F: Fn(&str) + Send + Sync + 'static,
+{
+  let mut child = command
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()?;
+
+  let lines: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+  let echo = Arc::new(echo);
+
+  let stderr = child.stderr.take().unwrap();
+  let stderr_lines = Arc::clone(&lines);
+  let stderr_echo = Arc::clone(&echo);
+  let stderr_thread = thread::spawn(move || collect_lines(stderr, &stderr_lines, stderr_echo.as_ref()));
+
+  let stdout = child.stdout.take().unwrap();
+  collect_lines(stdout, &lines, echo.as_ref());
+  stderr_thread.join().unwrap();
+
+  let status = child.wait()?;
+  let output = std::mem::take(&mut *lines.lock().unwrap());
+  Ok((status, output))
+}
+
+fn collect_lines<R: Read>(stream: R, lines: &Mutex<Vec<String>>, echo: &dyn Fn(&str)) {
+  for line in BufReader::new(stream).lines() {
+    let Ok(line) = line else {
+      break;
+    };
+    echo(line.as_str());
+    lines.lock().unwrap().push(line);
+  }
+}
+
+pub fn strip_ansi(line: &str) -> String {
+  let mut stripped = String::with_capacity(line.len());
+  let mut chars = line.chars();
+  while let Some(c) = chars.next() {
+    if c == '\x1b' {
+      for next in chars.by_ref() {
+        if next.is_ascii_alphabetic() {
+          break;
+        }
+      }
+      continue;
+    }
+    stripped.push(c);
+  }
+  stripped
+}
@@ -0,0 +1,155 @@
+use crate::fingerprint;
+use crate::hash;
+use crate::output::Event;
+use crate::toolchain;
+use crate::error::GojoError;
+
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use std::process::Command;
+use std::vec::Vec;
+
+// Accepted `--format` values and the CPack generators behind them.
+pub const FORMATS: [(&str, &str); 4] = [("tgz", "TGZ"), ("deb", "DEB"), ("rpm", "RPM"), ("zip", "ZIP")];
+
+pub fn generator(format: &str) -> Option<&'static str> {
+  FORMATS.iter().find(|(name, _)| *name == format).map(|(_, generator)| *generator)
+}
+
+pub struct Metadata<'a> {
+  pub name: &'a str,
+  pub version: &'a str,
+  pub description: &'a str,
+  pub maintainer: &'a str,
+  pub license: &'a str,
+}
+
+// Whether any of the project's own CMake files already has install() rules,
+// in which case the project decides what gets installed. Fetched
+// dependencies and build trees don't count.
+pub fn has_install_rules(project_root: &str, build_dir: &str) -> bool {
+  let mut files: Vec<String> = Vec::new();
+  fingerprint::collect_cmake_files(Path::new(project_root), build_dir, &mut files);
+  files.iter().any(|file| {
+    let Ok(contents) = fs::read_to_string(file) else {
+      return false;
+    };
+    contents
+      .lines()
+      .map(|line| line.split('#').next().unwrap_or("").trim_start().to_lowercase())
+      .any(|line| line.starts_with("install(") || line.starts_with("install ("))
+  })
+}
+
+// Installs the executable, for projects without install() rules of their
+// own.
+pub fn install_code(name: &str) -> String {
+  format!(
+    "if(TARGET {name})
+  install(TARGETS {name} RUNTIME DESTINATION bin)
+else()
+  message(WARNING \"gojo: no executable target '{name}' to install\")
+endif()"
+  )
+}
+
+// CMake code for the build hook that sets up CPack with the metadata from
+// the config. A project that includes CPack itself keeps its settings.
+pub fn cpack_code(project_root: &str, metadata: &Metadata) -> Vec<String> {
+  let mut code: Vec<String> = Vec::new();
+  let name = metadata.name;
+  let mut variables: Vec<(&str, String)> = vec![
+    ("CPACK_PACKAGE_NAME", name.to_string()),
+    ("CPACK_PACKAGE_VERSION", metadata.version.to_string()),
+    ("CPACK_DEBIAN_FILE_NAME", String::from("DEB-DEFAULT")),
+    ("CPACK_RPM_FILE_NAME", String::from("RPM-DEFAULT")),
+  ];
+  if !metadata.description.is_empty() {
+    variables.push(("CPACK_PACKAGE_DESCRIPTION_SUMMARY", metadata.description.to_string()));
+  }
+  if !metadata.maintainer.is_empty() {
+    variables.push(("CPACK_PACKAGE_CONTACT", metadata.maintainer.to_string()));
+    variables.push(("CPACK_PACKAGE_VENDOR", metadata.maintainer.to_string()));
+  }
+  if !metadata.license.is_empty() {
+    variables.push(("CPACK_RPM_PACKAGE_LICENSE", metadata.license.to_string()));
+  }
+  for file in ["LICENSE", "LICENSE.txt", "LICENSE.md", "COPYING"] {
+    let path = format!("{project_root}/{file}");
+    if Path::new(path.as_str()).is_file() {
+      variables.push(("CPACK_RESOURCE_FILE_LICENSE", path));
+      break;
+    }
+  }
+  for (variable, value) in variables {
+    code.push(format!("set({variable} \"{}\")", cmake_escape(value.as_str())));
+  }
+  code.push(String::from("include(CPack)"));
+  code
+}
+
+fn cmake_escape(value: &str) -> String {
+  value.replace('\\', "\\\\").replace('"', "\\\"").replace('$', "\\$")
+}
+
+// Runs CPack in the build directory and moves what it produced into
+// `dist_dir`, each file with a `.sha256` next to it in the format
+// `sha256sum -c` reads.
+pub fn run(build_dir: &str, generator: &str, dist_dir: &str) -> Result<()> {
+  let staging_dir = format!("{build_dir}/cpack");
+  let _ = fs::remove_dir_all(staging_dir.as_str());
+
+  status!("\x1b[1;35mPackaging\x1b[0m {} package\n", generator);
+  let cpack = Command::new("cpack")
+    .args(["-G", generator, "-B", staging_dir.as_str()])
+    .current_dir(build_dir)
+    .output()
+    .map_err(|e| toolchain::spawn_error("cpack", e))?;
+  if !cpack.status.success() {
+    return Err(GojoError::Failed(
+      format!(
+        "\x1b[31mCPack failed to create the package:\x1b[0m\n{}{}\n",
+        String::from_utf8_lossy(&cpack.stdout),
+        String::from_utf8_lossy(&cpack.stderr)
+      ),
+    ).into());
+  }
+
+  // CPack leaves its work tree next to the packages, only files at the top
+  // are artifacts.
+  let mut artifacts: Vec<String> = Vec::new();
+  for entry in fs::read_dir(staging_dir.as_str())?.flatten() {
+    if entry.path().is_file() {
+      artifacts.push(entry.file_name().to_string_lossy().to_string());
+    }
+  }
+  if artifacts.is_empty() {
+    return Err(GojoError::Failed(
+      "\x1b[31mno package created:\x1b[0m\n\tCPack finished without producing a package\n".to_string(),
+    ).into());
+  }
+  artifacts.sort();
+
+  fs::create_dir_all(dist_dir)?;
+  statusln!();
+  for artifact in artifacts {
+    let source = format!("{staging_dir}/{artifact}");
+    let destination = format!("{dist_dir}/{artifact}");
+    let contents = fs::read(source.as_str())?;
+    let checksum = hash::sha256_hex(&contents);
+    fs::write(destination.as_str(), &contents)?;
+    fs::write(format!("{destination}.sha256"), format!("{checksum}  {artifact}\n"))?;
+    let _ = fs::remove_file(source);
+
+    status!("  \x1b[1m{}\x1b[0m\n    sha256 {}\n", destination, checksum);
+    Event::new("package-created")
+      .with("format", generator.to_lowercase())
+      .with("path", destination.as_str())
+      .with("size", contents.len() as u64)
+      .with("sha256", checksum.as_str())
+      .emit();
+  }
+  statusln!();
+  Ok(())
+}
@@ -1,10 +1,12 @@
+use crate::output;
+use crate::error::GojoError;
+use crate::toolchain;
+
 use std::process::Command;
 use std::process::Stdio;
 use std::fs;
 use std::io::Result;
 use std::env;
-use std::io::Error;
-use std::io::ErrorKind;
 use std::path::PathBuf;
 use std::vec::Vec;
 
@@ -22,18 +24,23 @@ fn collect_files(path: PathBuf) -> Vec<String> {
 pub fn install_gtest() -> Result<()> {
   let tmp = env::home_dir().unwrap();
   let home = tmp.to_str().unwrap();
-  let result = Command::new("git").args(["clone", "https://github.com/google/googletest.git"]).current_dir(format!("{home}/.gojo/repos")).stdout(Stdio::inherit()).stderr(Stdio::inherit()).output()?;
+  let result = Command::new("git").args(["clone", "https://github.com/google/googletest.git"]).current_dir(format!("{home}/.gojo/repos")).stdout(output::child_stdout()).stderr(Stdio::inherit()).output().map_err(|e| toolchain::spawn_error("git", e))?;
   let status = result.status;
     if !status.success() {
-      return Err(Error::new(
-          ErrorKind::Other,
-          "\x1b[31mfailed to clone git repo\x1b[0m\n\n"
-      ));
+      return Err(GojoError::Failed(
+          "\x1b[31mfailed to clone git repo\x1b[0m\n\n".to_string()
+      ).into());
     }
 
   fs::create_dir_all(format!("{home}/.gojo/repos/googletest/build"))?;
-  Command::new("cmake").args([".."]).current_dir(format!("{home}/.gojo/repos/googletest/build")).stdout(Stdio::inherit()).stderr(Stdio::inherit()).output()?;
-  Command::new("cmake").args(["--build", "."]).current_dir(format!("{home}/.gojo/repos/googletest/build")).stdout(Stdio::inherit()).stderr(Stdio::inherit()).output()?;
+  let configure = Command::new("cmake").args([".."]).current_dir(format!("{home}/.gojo/repos/googletest/build")).stdout(output::child_stdout()).stderr(Stdio::inherit()).status().map_err(|e| toolchain::spawn_error("cmake", e))?;
+  if !configure.success() {
+    return Err(GojoError::ConfigureFailed("\x1b[31mCMake failed to configure googletest\x1b[0m\n\n".to_string()).into());
+  }
+  let build = Command::new("cmake").args(["--build", "."]).current_dir(format!("{home}/.gojo/repos/googletest/build")).stdout(output::child_stdout()).stderr(Stdio::inherit()).status().map_err(|e| toolchain::spawn_error("cmake", e))?;
+  if !build.success() {
+    return Err(GojoError::BuildFailed("\x1b[31mCMake failed to build googletest\x1b[0m\n\n".to_string()).into());
+  }
 
   fs::create_dir_all(format!("{home}/.gojo/lib/gtest"))?;
   fs::create_dir_all(format!("{home}/.gojo/lib/gmock"))?;
@@ -53,6 +60,6 @@ pub fn install_gtest() -> Result<()> {
   for file in mock_include_files {
     fs::copy(format!("{home}/.gojo/repos/googletest/googlemock/include/gmock/{file}"), format!("{home}/.gojo/include/gmock/{file}"))?;
   }
-  println!("gtest successfully installed");
+  statusln!("gtest successfully installed");
   Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,192 @@
+use crate::output;
+use crate::output::Event;
+use crate::toolchain;
+use crate::error::GojoError;
+
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+use std::time;
+use std::vec::Vec;
+
+// Each binary is timed this many times and the fastest run is kept, which
+// filters out most of the noise from other processes.
+const TIMING_RUNS: usize = 3;
+
+// The profile is written outside the build directories, which are free to
+// be rebuilt independently. GCC names each .gcda after its object's path,
+// so that path is made relative to the build directory on both sides.
+pub fn instrument_flags(clang: bool, profile_dir: &str, build_dir: &str) -> String {
+  if clang {
+    return format!("-fprofile-generate={profile_dir}");
+  }
+  format!("-fprofile-generate={profile_dir} -fprofile-prefix-path={build_dir} -fprofile-update=atomic")
+}
+
+pub fn use_flags(clang: bool, profile_dir: &str, build_dir: &str) -> String {
+  if clang {
+    return format!("-fprofile-use={profile_dir}/merged.profdata -Wno-profile-instr-unprofiled -Wno-profile-instr-out-of-date");
+  }
+  format!("-fprofile-use={profile_dir} -fprofile-prefix-path={build_dir} -fprofile-correction -Wno-missing-profile")
+}
+
+// What the instrumented binary runs to produce a profile: the configured
+// `pgo_train` command with `{bin}` replaced by the binary, or the test suite.
+fn workload(train: &str, build_dir: &str, bin: &str) -> Command {
+  if train.is_empty() {
+    let mut ctest = Command::new("ctest");
+    ctest.current_dir(build_dir);
+    return ctest;
+  }
+  let mut shell = Command::new("sh");
+  shell.args(["-c", train.replace("{bin}", bin).as_str()]);
+  shell
+}
+
+pub fn train(train: &str, build_dir: &str, bin: &str, profile_dir: &str) -> Result<()> {
+  let _ = fs::remove_dir_all(profile_dir);
+  fs::create_dir_all(profile_dir)?;
+
+  status!("\x1b[1;35mTraining\x1b[0m {}\n\n", if train.is_empty() { "with the test suite" } else { train });
+  let status = workload(train, build_dir, bin)
+    .stdout(output::child_stdout())
+    .stderr(Stdio::inherit())
+    .status()?;
+  if !status.success() {
+    return Err(GojoError::Failed(
+      "\x1b[31mtraining failed:\x1b[0m\n\tthe training workload exited with an error, its profile would be incomplete\n".to_string(),
+    ).into());
+  }
+  Ok(())
+}
+
+// Clang's raw profiles must be merged before they can be used. GCC reads its
+// .gcda files directly.
+pub fn merge(clang: bool, profile_dir: &str) -> Result<()> {
+  let mut profiles: Vec<String> = Vec::new();
+  find_profiles(Path::new(profile_dir), if clang { ".profraw" } else { ".gcda" }, &mut profiles);
+  if profiles.is_empty() {
+    return Err(GojoError::Failed(
+      "\x1b[31mno profile data found:\x1b[0m\n\tthe training workload never ran the instrumented code\n".to_string(),
+    ).into());
+  }
+  if !clang {
+    return Ok(());
+  }
+
+  let merged = Command::new("llvm-profdata")
+    .args(["merge", "-o", format!("{profile_dir}/merged.profdata").as_str()])
+    .args(profiles.iter())
+    .stdout(Stdio::null())
+    .status()
+    .map_err(|e| toolchain::spawn_error("llvm-profdata", e))?;
+  if !merged.success() {
+    return Err(GojoError::Failed(
+      "\x1b[31mllvm-profdata failed:\x1b[0m\n\tcould not merge the raw profiles\n".to_string(),
+    ).into());
+  }
+  Ok(())
+}
+
+// Compares the binary and the workload's run time with and without the
+// profile applied.
+pub fn report(
+  train: &str,
+  baseline_dir: &str,
+  baseline_bin: Option<&str>,
+  optimized_dir: &str,
+  optimized_bin: Option<&str>,
+) -> Result<()> {
+  let baseline_size = baseline_bin.and_then(|bin| fs::metadata(bin).ok()).map(|m| m.len());
+  let optimized_size = optimized_bin.and_then(|bin| fs::metadata(bin).ok()).map(|m| m.len());
+
+  status!("\x1b[1;35mTiming\x1b[0m best of {} runs\n", TIMING_RUNS);
+  let baseline_ms = time_workload(train, baseline_dir, baseline_bin.unwrap_or(""))?;
+  let optimized_ms = time_workload(train, optimized_dir, optimized_bin.unwrap_or(""))?;
+
+  status!("\n\x1b[1;35mProfile-guided optimization\x1b[0m\n");
+  status!("  {:<6}  {:>12}  {:>12}  {:>8}\n", "", "baseline", "optimized", "change");
+  if let (Some(before), Some(after)) = (baseline_size, optimized_size) {
+    status!(
+      "  {:<6}  {:>12}  {:>12}  {}\n",
+      "size",
+      format_size(before),
+      format_size(after),
+      format_change(before as f64, after as f64)
+    );
+  }
+  status!(
+    "  {:<6}  {:>11.3}s  {:>11.3}s  {}\n\n",
+    "time",
+    baseline_ms as f64 / 1000.0,
+    optimized_ms as f64 / 1000.0,
+    format_change(baseline_ms as f64, optimized_ms as f64)
+  );
+  status!("\x1b[1;35mOptimized build in\x1b[0m {}\n\n", optimized_dir);
+
+  Event::new("pgo-report")
+    .with("build_dir", optimized_dir)
+    .with("baseline_size", baseline_size)
+    .with("optimized_size", optimized_size)
+    .with("baseline_ms", baseline_ms)
+    .with("optimized_ms", optimized_ms)
+    .emit();
+  Ok(())
+}
+
+fn time_workload(train: &str, build_dir: &str, bin: &str) -> Result<u64> {
+  let mut best: Option<u64> = None;
+  for _ in 0..TIMING_RUNS {
+    let start = time::Instant::now();
+    let status = workload(train, build_dir, bin)
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .status()?;
+    let elapsed = start.elapsed().as_millis() as u64;
+    if !status.success() {
+      return Err(GojoError::Failed(
+        format!(
+          "\x1b[31mworkload failed:\x1b[0m\n\tthe training workload failed against {}\n",
+          Path::new(build_dir).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+        ),
+      ).into());
+    }
+    best = Some(best.map_or(elapsed, |best| best.min(elapsed)));
+  }
+  Ok(best.unwrap_or(0))
+}
+
+fn find_profiles(dir: &Path, extension: &str, profiles: &mut Vec<String>) {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if path.is_dir() {
+      find_profiles(path.as_path(), extension, profiles);
+    } else if entry.file_name().to_string_lossy().ends_with(extension) {
+      profiles.push(path.to_string_lossy().to_string());
+    }
+  }
+}
+
+fn format_size(bytes: u64) -> String {
+  if bytes >= 1024 * 1024 {
+    return format!("{:.2} MiB", bytes as f64 / (1024.0 * 1024.0));
+  }
+  format!("{:.1} KiB", bytes as f64 / 1024.0)
+}
+
+fn format_change(before: f64, after: f64) -> String {
+  if before == 0.0 {
+    return format!("{:>8}", "-");
+  }
+  let change = (after - before) * 100.0 / before;
+  let text = format!("{:>+7.1}%", change);
+  if change < 0.0 {
+    return format!("\x1b[32m{text}\x1b[0m");
+  }
+  text
+}
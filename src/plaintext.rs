@@ -6,13 +6,31 @@ pub const HELP: &str =
 \x1b[1;35mCommands:\x1b[0m
     \x1b[1;35minit\x1b[0m \x1b[0;36m<name>\x1b[0m [options]       create new gojo project in current directory
     \x1b[1;35mbuild\x1b[0m [options]\x1b[0m             build project with CMake
-    \x1b[1;35mrun\x1b[0m [options]\x1b[0m               run compiled executable
-    \x1b[1;35mtest\x1b[0m                        build and run unit tests
+    \x1b[1;35mrun\x1b[0m [options]\x1b[0m               run an executable, see --bin and --example
+    \x1b[1;35mdebug\x1b[0m [options]             start gdb or lldb on a debug build
+    \x1b[1;35mtest\x1b[0m [pattern]              build and run unit tests, see --list
+    \x1b[1;35mcoverage\x1b[0m                    run unit tests and report code coverage
+    \x1b[1;35mpgo\x1b[0m                         build with profile-guided optimization
+    \x1b[1;35mprofile\x1b[0m [options]           record a CPU profile and draw a flame graph
+    \x1b[1;35mpackage\x1b[0m [options]           build a release package into dist/
+    \x1b[1;35mdeploy\x1b[0m [options]            install the project into a prefix
+    \x1b[1;35mundeploy\x1b[0m                    remove what deploy installed
+    \x1b[1;35mcache\x1b[0m \x1b[0;36m<stats|clear>\x1b[0m         show or empty the shared object cache
     \x1b[1;35mclean\x1b[0m                       remove build files and CMake cache
     \x1b[1;35mfmt\x1b[0m [options]               auto-format your code
     \x1b[1;35mcheck\x1b[0m                       run static code analyzers
+    \x1b[1;35mwatch\x1b[0m [command] [options]   rerun build, test, run or check on file changes
     \x1b[1;35mhelp\x1b[0m                        print help
 
+\x1b[1;35mGlobal options:\x1b[0m
+    --message-format \x1b[0;36m<human|json>\x1b[0m  print newline-delimited JSON events instead of text
+
+\x1b[1;35mExit codes:\x1b[0m
+    0 success, 1 other failure, 2 incorrect usage, 3 invalid config, 4 missing tool,
+    5 configure failed, 6 build failed, 7 tests failed, 8 checks failed,
+    'run' and 'debug' pass on the program's or debugger's own code, which can
+    overlap with the codes above
+
 See '\x1b[0;35mgojo\x1b[0m \x1b[0;36m<command>\x1b[0m --help' for more information on a specific command
 ";
 
@@ -28,10 +46,12 @@ Testing
 cmake_install.cmake
 install_manifest.txt
 compile_commands.json
+.clangd
 CTestTestfile.cmake
 _deps
 CMakeUserPresets.json
 build
+dist
 .cache
 .vscode
 .DS_Store";
@@ -0,0 +1,235 @@
+use crate::error::GojoError;
+use crate::hash;
+use crate::output::Event;
+use crate::toolchain;
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Result;
+use std::process::Command;
+use std::process::Stdio;
+use std::vec::Vec;
+
+// Frame pointers let perf walk the stack cheaply, debug info names what it
+// finds.
+pub const FLAGS: &str = "-g -fno-omit-frame-pointer";
+
+// Samples per second, off the round numbers so sampling doesn't line up
+// with the program's own timers.
+const FREQUENCY: &str = "999";
+
+pub fn record(program: &str, args: &[String], data_file: &str) -> Result<()> {
+  status!("\x1b[1;35mProfiling\x1b[0m {}\n\n", program);
+  let status = Command::new("perf")
+    .args(["record", "-F", FREQUENCY, "--call-graph", "fp", "-o", data_file, "--", program])
+    .args(args)
+    .stdin(Stdio::inherit())
+    .stdout(Stdio::inherit())
+    .stderr(Stdio::inherit())
+    .status()
+    .map_err(|e| toolchain::spawn_error("perf", e))?;
+  if !fs::exists(data_file)? {
+    return Err(GojoError::Failed(String::from(
+      "\x1b[31mperf failed to record a profile:\x1b[0m\n\tcheck /proc/sys/kernel/perf_event_paranoid, perf needs it at 2 or lower\n",
+    ))
+    .into());
+  }
+  if !status.success() {
+    status!("\x1b[33mthe program exited unsuccessfully, the profile covers what it ran\x1b[0m\n");
+  }
+  Ok(())
+}
+
+// The recorded stacks folded into `root;...;leaf` lines with how often
+// each was sampled, the format flamegraph tools share.
+pub fn fold(data_file: &str) -> Result<BTreeMap<String, u64>> {
+  let output = Command::new("perf")
+    .args(["script", "-F", "comm,ip,sym", "-i", data_file])
+    .stdin(Stdio::null())
+    .stderr(Stdio::null())
+    .output()
+    .map_err(|e| toolchain::spawn_error("perf", e))?;
+
+  let mut stacks: BTreeMap<String, u64> = BTreeMap::new();
+  let mut comm = String::new();
+  let mut frames: Vec<String> = Vec::new();
+  let mut flush = |comm: &str, frames: &mut Vec<String>| {
+    if comm.is_empty() {
+      return;
+    }
+    let mut stack = vec![comm.to_string()];
+    stack.extend(frames.drain(..).rev());
+    *stacks.entry(stack.join(";")).or_insert(0) += 1;
+  };
+  for line in String::from_utf8_lossy(&output.stdout).lines() {
+    if line.trim().is_empty() {
+      flush(comm.as_str(), &mut frames);
+      comm.clear();
+    } else if line.starts_with(char::is_whitespace) {
+      // `<ip> <symbol>`, where the symbol may have spaces of its own.
+      let symbol = line.trim().split_once(' ').map(|(_, symbol)| symbol.trim()).unwrap_or("[unknown]");
+      // A `;` would split the frame in the folded format.
+      frames.push(symbol.replace(';', ":"));
+    } else {
+      flush(comm.as_str(), &mut frames);
+      comm = line.trim().to_string();
+    }
+  }
+  flush(comm.as_str(), &mut frames);
+  Ok(stacks)
+}
+
+pub fn write_folded(stacks: &BTreeMap<String, u64>, path: &str) -> Result<()> {
+  let contents: String = stacks.iter().map(|(stack, count)| format!("{stack} {count}\n")).collect();
+  fs::write(path, contents)
+}
+
+// Prints the functions the most samples were taken in, with the share of
+// samples spent in the function itself and in everything it called.
+pub fn print_top(stacks: &BTreeMap<String, u64>, count: usize) {
+  let total: u64 = stacks.values().sum();
+  if total == 0 {
+    status!("\x1b[33mno samples were recorded\x1b[0m\n\n");
+    return;
+  }
+  let mut own: HashMap<&str, u64> = HashMap::new();
+  let mut inclusive: HashMap<&str, u64> = HashMap::new();
+  for (stack, samples) in stacks.iter() {
+    // The first frame is the program's name, not a function.
+    let frames: Vec<&str> = stack.split(';').skip(1).collect();
+    if let Some(leaf) = frames.last() {
+      *own.entry(leaf).or_insert(0) += samples;
+    }
+    let mut seen: Vec<&str> = Vec::new();
+    for frame in frames {
+      // Recursive functions count once per sample.
+      if !seen.contains(&frame) {
+        seen.push(frame);
+        *inclusive.entry(frame).or_insert(0) += samples;
+      }
+    }
+  }
+
+  let mut hottest: Vec<(&str, u64)> = own.into_iter().collect();
+  hottest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+  let percent = |samples: u64| samples as f64 * 100.0 / total as f64;
+
+  status!("\n\x1b[1;35mHottest functions\x1b[0m ({} samples)\n", total);
+  status!("  {:>7} {:>7}  function\n", "self", "total");
+  for (function, samples) in hottest.iter().take(count) {
+    let total_samples = inclusive.get(function).copied().unwrap_or(*samples);
+    status!("  {:>6.2}% {:>6.2}%  {}\n", percent(*samples), percent(total_samples), function);
+    Event::new("profile-function")
+      .with("function", *function)
+      .with("self_samples", *samples)
+      .with("total_samples", total_samples)
+      .emit();
+  }
+  statusln!();
+}
+
+const WIDTH: f64 = 1200.0;
+const FRAME_HEIGHT: f64 = 16.0;
+const FONT_SIZE: f64 = 12.0;
+// Roughly how wide a character of the font is, to fit labels in frames.
+const CHAR_WIDTH: f64 = FONT_SIZE * 0.59;
+const PADDING: f64 = 10.0;
+// Frames narrower than this would be invisible and are left out.
+const MIN_WIDTH: f64 = 0.1;
+
+#[derive(Default)]
+struct Node {
+  samples: u64,
+  children: BTreeMap<String, Node>,
+}
+
+// A flame graph of the folded stacks as a standalone SVG: one box per
+// frame, as wide as its share of the samples, callers below callees.
+// Hovering a box shows its full name and sample count.
+pub fn flamegraph(stacks: &BTreeMap<String, u64>, title: &str) -> String {
+  let mut root = Node::default();
+  for (stack, samples) in stacks.iter() {
+    root.samples += samples;
+    let mut node = &mut root;
+    for frame in stack.split(';') {
+      node = node.children.entry(frame.to_string()).or_default();
+      node.samples += samples;
+    }
+  }
+
+  let depth = max_depth(&root);
+  let height = (depth as f64 + 1.0) * FRAME_HEIGHT + PADDING * 4.0;
+  let scale = if root.samples > 0 { (WIDTH - PADDING * 2.0) / root.samples as f64 } else { 0.0 };
+  let mut frames = String::new();
+  draw(&mut frames, "all", &root, 0, PADDING, &Layout { total: root.samples, scale, height });
+
+  format!(
+    r##"<?xml version="1.0" standalone="no"?>
+<svg version="1.1" width="{WIDTH}" height="{height}" viewBox="0 0 {WIDTH} {height}" xmlns="http://www.w3.org/2000/svg">
+<style>text {{ font-family: Verdana, sans-serif; font-size: {FONT_SIZE}px; fill: #000; }} rect {{ stroke: #fff; stroke-width: 0.5; }}</style>
+<rect x="0" y="0" width="{WIDTH}" height="{height}" fill="#f8f8f8" style="stroke: none"/>
+<text x="{}" y="{}" text-anchor="middle" style="font-size: 17px">{}</text>
+{frames}</svg>
+"##,
+    WIDTH / 2.0,
+    PADDING * 2.0,
+    escape(title)
+  )
+}
+
+fn max_depth(node: &Node) -> usize {
+  node.children.values().map(|child| max_depth(child) + 1).max().unwrap_or(0)
+}
+
+struct Layout {
+  total: u64,
+  scale: f64,
+  height: f64,
+}
+
+fn draw(svg: &mut String, name: &str, node: &Node, depth: usize, x: f64, layout: &Layout) {
+  let width = node.samples as f64 * layout.scale;
+  if width < MIN_WIDTH {
+    return;
+  }
+  let y = layout.height - PADDING - (depth as f64 + 1.0) * FRAME_HEIGHT;
+  let percent = node.samples as f64 * 100.0 / layout.total.max(1) as f64;
+  svg.push_str(
+    format!(
+      "<g><title>{} ({} samples, {percent:.2}%)</title><rect x=\"{x:.1}\" y=\"{y:.1}\" width=\"{width:.1}\" height=\"{}\" fill=\"{}\"/>",
+      escape(name),
+      node.samples,
+      FRAME_HEIGHT - 1.0,
+      color(name)
+    )
+    .as_str(),
+  );
+  let fits = ((width - 6.0) / CHAR_WIDTH).floor().max(0.0) as usize;
+  if fits >= 3 {
+    let label = if name.chars().count() > fits {
+      format!("{}..", name.chars().take(fits - 2).collect::<String>())
+    } else {
+      name.to_string()
+    };
+    svg.push_str(format!("<text x=\"{:.1}\" y=\"{:.1}\">{}</text>", x + 3.0, y + FRAME_HEIGHT - 4.5, escape(label.as_str())).as_str());
+  }
+  svg.push_str("</g>\n");
+
+  let mut child_x = x;
+  for (child_name, child) in node.children.iter() {
+    draw(svg, child_name, child, depth + 1, child_x, layout);
+    child_x += child.samples as f64 * layout.scale;
+  }
+}
+
+// Warm colors, the same for a function every time it appears.
+fn color(name: &str) -> String {
+  let hash = hash::fnv1a_hex(name.as_bytes());
+  let byte = |idx: usize| u8::from_str_radix(&hash[idx..idx + 2], 16).unwrap_or(0) as u32;
+  format!("rgb({},{},{})", 205 + byte(0) * 50 / 255, byte(2) * 230 / 255, byte(4) * 55 / 255)
+}
+
+fn escape(text: &str) -> String {
+  text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
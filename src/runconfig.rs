@@ -0,0 +1,173 @@
+use crate::error::GojoError;
+
+use std::fs;
+use std::io::Error;
+use std::io::Result;
+use std::path::Path;
+use std::process::Command;
+use std::vec::Vec;
+
+// A named way to run the program, from `run.<name>.<field>: <value>` lines
+// in the config:
+//   run.bench.args: --iterations 1000 "input with spaces.txt"
+//   run.bench.env: LOG_LEVEL=warn THREADS=4
+//   run.bench.cwd: data
+//   run.bench.stdin: data/input.txt
+// Paths are relative to the project root. `env` may be repeated.
+pub struct RunConfig {
+  pub name: String,
+  pub args: Vec<String>,
+  pub env: Vec<(String, String)>,
+  pub cwd: Option<String>,
+  pub stdin: Option<String>,
+}
+
+pub fn find(lines: &[(String, String)], name: &str, project_root: &str) -> Result<RunConfig> {
+  let mut config = RunConfig { name: name.to_string(), args: Vec::new(), env: Vec::new(), cwd: None, stdin: None };
+  let mut found = false;
+  for (key, value) in lines {
+    let Some((config_name, field)) = key.rsplit_once('.') else {
+      continue;
+    };
+    if config_name != name {
+      continue;
+    }
+    found = true;
+    match field {
+      "args" => config.args.extend(split_words(value).map_err(|e| invalid(key, e.as_str()))?),
+      "env" => {
+        for word in split_words(value).map_err(|e| invalid(key, e.as_str()))? {
+          let Some((var, val)) = word.split_once('=') else {
+            return Err(invalid(key, format!("expected VAR=value, found '{word}'").as_str()));
+          };
+          config.env.push((var.to_string(), val.to_string()));
+        }
+      }
+      "cwd" => config.cwd = Some(resolve(project_root, value)),
+      "stdin" => config.stdin = Some(resolve(project_root, value)),
+      _ => return Err(invalid(key, "the field must be one of args, env, cwd or stdin")),
+    }
+  }
+  if !found {
+    let mut names: Vec<&str> = lines.iter().filter_map(|(key, _)| key.rsplit_once('.').map(|(name, _)| name)).collect();
+    names.sort();
+    names.dedup();
+    let available = if names.is_empty() { String::from("none") } else { names.join(", ") };
+    return Err(
+      GojoError::Usage(format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\tno run configuration named '{name}'\n\tavailable configurations: {available}\n"
+      ))
+      .into(),
+    );
+  }
+  Ok(config)
+}
+
+fn resolve(project_root: &str, path: &str) -> String {
+  if Path::new(path).is_absolute() {
+    return path.to_string();
+  }
+  format!("{project_root}/{path}")
+}
+
+fn invalid(key: &str, reason: &str) -> Error {
+  GojoError::Config(format!("\x1b[31minvalid run configuration:\x1b[0m\n\t'run.{key}': {reason}\n")).into()
+}
+
+// Splits a line into words the way a shell would for plain words and
+// quotes: single quotes keep everything, double quotes and bare words
+// honor backslash escapes.
+pub fn split_words(line: &str) -> std::result::Result<Vec<String>, String> {
+  let mut words: Vec<String> = Vec::new();
+  let mut word = String::new();
+  let mut in_word = false;
+  let mut chars = line.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '\'' => {
+        in_word = true;
+        loop {
+          match chars.next() {
+            Some('\'') => break,
+            Some(c) => word.push(c),
+            None => return Err(String::from("unterminated single quote")),
+          }
+        }
+      }
+      '"' => {
+        in_word = true;
+        loop {
+          match chars.next() {
+            Some('"') => break,
+            Some('\\') => word.push(chars.next().ok_or_else(|| String::from("unterminated double quote"))?),
+            Some(c) => word.push(c),
+            None => return Err(String::from("unterminated double quote")),
+          }
+        }
+      }
+      '\\' => {
+        in_word = true;
+        if let Some(c) = chars.next() {
+          word.push(c);
+        }
+      }
+      c if c.is_whitespace() => {
+        if in_word {
+          words.push(std::mem::take(&mut word));
+          in_word = false;
+        }
+      }
+      c => {
+        in_word = true;
+        word.push(c);
+      }
+    }
+  }
+  if in_word {
+    words.push(word);
+  }
+  Ok(words)
+}
+
+// Variables from the project's `.env`, one `VAR=value` per line, with
+// optional `export` and quotes, and `#` comments.
+pub fn dotenv(project_root: &str) -> Result<Vec<(String, String)>> {
+  let path = format!("{project_root}/.env");
+  let Ok(contents) = fs::read_to_string(path.as_str()) else {
+    return Ok(Vec::new());
+  };
+  let mut vars: Vec<(String, String)> = Vec::new();
+  for (idx, line) in contents.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+    let Some((var, value)) = line.split_once('=') else {
+      return Err(
+        GojoError::Config(format!("\x1b[31minvalid .env:\x1b[0m\n\tline {}: expected VAR=value\n", idx + 1)).into(),
+      );
+    };
+    let value = value.trim();
+    let value = if value.starts_with('"') || value.starts_with('\'') {
+      split_words(value)
+        .map_err(|e| -> Error { GojoError::Config(format!("\x1b[31minvalid .env:\x1b[0m\n\tline {}: {e}\n", idx + 1)).into() })?
+        .join(" ")
+    } else {
+      // Unquoted values end at a comment.
+      value.split(" #").next().unwrap_or("").trim_end().to_string()
+    };
+    vars.push((var.trim().to_string(), value));
+  }
+  Ok(vars)
+}
+
+// The `.env` variables a program gojo starts gets. Variables already in
+// gojo's own environment win, so `FOO=1 gojo run` overrides the file.
+pub fn dotenv_additions(vars: &[(String, String)]) -> Vec<(String, String)> {
+  vars.iter().filter(|(var, _)| std::env::var_os(var).is_none()).cloned().collect()
+}
+
+pub fn apply_dotenv(command: &mut Command, vars: &[(String, String)]) {
+  command.envs(dotenv_additions(vars));
+}
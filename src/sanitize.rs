@@ -0,0 +1,160 @@
+use crate::toolchain;
+use crate::error::GojoError;
+
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Error;
+use std::io::Result;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::thread;
+use std::vec::Vec;
+
+pub const SANITIZERS: &[&str] = &["address", "undefined", "thread", "memory"];
+
+// Sanitizers that can't share a binary.
+const CONFLICTS: &[(&str, &str)] = &[
+  ("address", "thread"),
+  ("address", "memory"),
+  ("thread", "memory"),
+];
+
+// Default runtime options, applied unless the variable is already set.
+const RUNTIME_OPTIONS: &[(&str, &str, &str)] = &[
+  (
+    "address",
+    "ASAN_OPTIONS",
+    "detect_leaks=1:detect_stack_use_after_return=1:strict_string_checks=1:check_initialization_order=1",
+  ),
+  ("undefined", "UBSAN_OPTIONS", "print_stacktrace=1:halt_on_error=1"),
+  ("thread", "TSAN_OPTIONS", "halt_on_error=1:second_deadlock_stack=1"),
+  ("memory", "MSAN_OPTIONS", "halt_on_error=1:poison_in_dtor=1"),
+];
+
+// First line of each sanitizer's report.
+const REPORT_MARKERS: &[&str] = &[
+  "ERROR: AddressSanitizer",
+  "ERROR: LeakSanitizer",
+  "WARNING: ThreadSanitizer",
+  "WARNING: MemorySanitizer",
+  "ERROR: MemorySanitizer",
+  "runtime error:",
+];
+
+// Parses a comma separated `--sanitize` value into a sorted, deduplicated
+// list of sanitizers that can be combined in one build.
+pub fn parse(list: &str) -> Result<Vec<String>> {
+  let mut sanitizers: Vec<String> = Vec::new();
+  for name in list.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+    if !SANITIZERS.contains(&name) {
+      return Err(GojoError::Usage(
+        format!(
+          "\x1b[31mincorrect usage:\x1b[0m\n\tunknown sanitizer '{}', expected one of: {}\n",
+          name,
+          SANITIZERS.join(", ")
+        ),
+      ).into());
+    }
+    if !sanitizers.iter().any(|s| s == name) {
+      sanitizers.push(name.to_string());
+    }
+  }
+  if sanitizers.is_empty() {
+    return Err(GojoError::Usage(
+      format!(
+        "\x1b[31mincorrect usage:\x1b[0m\n\t--sanitize expects one or more of: {}\n",
+        SANITIZERS.join(", ")
+      ),
+    ).into());
+  }
+  sanitizers.sort();
+
+  for (a, b) in CONFLICTS {
+    if sanitizers.iter().any(|s| s == a) && sanitizers.iter().any(|s| s == b) {
+      return Err(GojoError::Usage(
+        format!("\x1b[31mincorrect usage:\x1b[0m\n\tthe {a} and {b} sanitizers can't be combined\n"),
+      ).into());
+    }
+  }
+  Ok(sanitizers)
+}
+
+// Checks the sanitizers are available for the current compiler.
+pub fn check_supported(sanitizers: &[String]) -> Result<()> {
+  if sanitizers.iter().any(|s| s == "memory") && !toolchain::is_clang() {
+    return Err(GojoError::Config(
+      "\x1b[31munsupported sanitizer:\x1b[0m\n\tthe memory sanitizer requires Clang, set CXX=clang++\n".to_string(),
+    ).into());
+  }
+  Ok(())
+}
+
+// Each combination builds into its own directory so instrumented and
+// regular objects never mix.
+pub fn build_dir(base: &str, sanitizers: &[String]) -> String {
+  if sanitizers.is_empty() {
+    return base.to_string();
+  }
+  format!("{}/sanitize-{}", base, sanitizers.join("-"))
+}
+
+pub fn compile_flags(sanitizers: &[String]) -> String {
+  let mut flags = format!("-fsanitize={} -fno-omit-frame-pointer -g", sanitizers.join(","));
+  if sanitizers.iter().any(|s| s == "memory") {
+    flags += " -fsanitize-memory-track-origins";
+  }
+  flags
+}
+
+pub fn link_flags(sanitizers: &[String]) -> String {
+  format!("-fsanitize={}", sanitizers.join(","))
+}
+
+pub fn set_runtime_options(command: &mut Command, sanitizers: &[String]) {
+  for (sanitizer, var, options) in RUNTIME_OPTIONS {
+    if sanitizers.iter().any(|s| s == sanitizer) && std::env::var(var).is_err() {
+      command.env(var, options);
+    }
+  }
+}
+
+pub fn find_report(line: &str) -> Option<String> {
+  if REPORT_MARKERS.iter().any(|marker| line.contains(marker)) {
+    return Some(line.trim().to_string());
+  }
+  None
+}
+
+// Runs `command` with stdin and stdout left as the caller set them, the
+// terminal by default, while its stderr, where sanitizer reports go, is
+// both echoed and scanned.
+pub fn run_and_scan(command: &mut Command) -> Result<(ExitStatus, Option<String>)> {
+  let mut child = command
+    .stderr(Stdio::piped())
+    .spawn()?;
+
+  let stderr = child.stderr.take().unwrap();
+  let scanner = thread::spawn(move || {
+    let mut report: Option<String> = None;
+    for line in BufReader::new(stderr).lines() {
+      let Ok(line) = line else {
+        break;
+      };
+      eprintln!("{line}");
+      if report.is_none() {
+        report = find_report(line.as_str());
+      }
+    }
+    report
+  });
+
+  let status = child.wait()?;
+  let report = scanner.join().unwrap();
+  Ok((status, report))
+}
+
+// `kind` is how the command failed, like `GojoError::TestFailed` for tests.
+pub fn report_error(report: &str, kind: fn(String) -> GojoError) -> Error {
+  kind(format!("\x1b[31msanitizer error detected:\x1b[0m\n\t{report}\n")).into()
+}
@@ -0,0 +1,464 @@
+use crate::ctest;
+use crate::error::GojoError;
+use crate::json::Value;
+use crate::output;
+use crate::xml;
+
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+use std::vec::Vec;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+  Junit,
+  Json,
+}
+
+// A `--report <format>=<path>` option of `gojo test`.
+pub struct Report {
+  pub format: Format,
+  pub path: String,
+}
+
+pub fn parse(value: &str) -> Result<Report> {
+  let (format, path) = value.split_once('=').unwrap_or((value, ""));
+  let format = match format {
+    "junit" => Format::Junit,
+    "json" => Format::Json,
+    _ => {
+      return Err(
+        GojoError::Usage(format!(
+          "\x1b[31mincorrect usage:\x1b[0m\n\tunknown report format '{format}', expected junit or json\n\tsee 'gojo test --help'\n"
+        ))
+        .into(),
+      )
+    }
+  };
+  if path.is_empty() {
+    return Err(
+      GojoError::Usage(String::from(
+        "\x1b[31mincorrect usage:\x1b[0m\n\t'--report' requires <format>=<path>, as in junit=report.xml\n",
+      ))
+      .into(),
+    );
+  }
+  Ok(Report { format, path: path.to_string() })
+}
+
+// ctest writes JUnit XML, with each test's output, since CMake 3.21.
+pub fn ctest_writes_junit() -> bool {
+  let Ok(output) = Command::new("ctest").arg("--version").stdin(Stdio::null()).stderr(Stdio::null()).output() else {
+    return false;
+  };
+  // `ctest version 3.28.1`
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  let mut version = stdout.split_whitespace().nth(2).unwrap_or("").split('.').map(|part| part.parse::<u32>().unwrap_or(0));
+  (version.next().unwrap_or(0), version.next().unwrap_or(0)) >= (3, 21)
+}
+
+// One test case in the report. Whole GoogleTest binaries are reported as
+// the cases they ran.
+pub struct Case {
+  pub suite: String,
+  pub name: String,
+  // passed, failed, skipped, timeout or crashed, as in `test-result` events.
+  pub status: String,
+  pub duration_ms: u64,
+  pub failures: Vec<String>,
+  // What the test printed. Cases of one binary share its output.
+  pub output: String,
+}
+
+// What ctest's JUnit file knows about a test that its summary lines don't.
+struct CtestDetails {
+  name: String,
+  failure: Option<String>,
+  output: String,
+}
+
+fn read_ctest_junit(path: &str) -> Vec<CtestDetails> {
+  let Some(root) = fs::read_to_string(path).ok().and_then(|text| xml::parse(text.as_str())) else {
+    return Vec::new();
+  };
+  root
+    .children("testcase")
+    .filter_map(|testcase| {
+      Some(CtestDetails {
+        name: testcase.attribute("name")?.to_string(),
+        failure: testcase.child("failure").and_then(|failure| failure.attribute("message")).map(String::from),
+        output: testcase.child("system-out").map(|out| out.text.clone()).unwrap_or_default(),
+      })
+    })
+    .collect()
+}
+
+// Every XML file GoogleTest wrote to `dir`, as the name of the file and the
+// cases in it. GoogleTest names the files after the program, numbering them
+// when the program runs more than once.
+fn read_gtest_reports(dir: &str) -> Vec<(String, Vec<Case>)> {
+  let mut files: Vec<_> = fs::read_dir(dir).map(|entries| entries.flatten().map(|entry| entry.path()).collect()).unwrap_or_default();
+  files.sort();
+
+  let mut reports: Vec<(String, Vec<Case>)> = Vec::new();
+  for file in files {
+    let Some(stem) = file.file_stem().map(|stem| stem.to_string_lossy().to_string()) else {
+      continue;
+    };
+    let Some(root) = fs::read_to_string(file.as_path()).ok().and_then(|text| xml::parse(text.as_str())) else {
+      continue;
+    };
+    let mut cases: Vec<Case> = Vec::new();
+    for testcase in root.children("testsuite").flat_map(|suite| suite.children("testcase")) {
+      let failures: Vec<String> = testcase
+        .children("failure")
+        .map(|failure| match failure.text.trim() {
+          "" => failure.attribute("message").unwrap_or_default().to_string(),
+          text => text.to_string(),
+        })
+        .collect();
+      let skipped = testcase.attribute("status") == Some("notrun")
+        || testcase.attribute("result") == Some("skipped")
+        || testcase.child("skipped").is_some();
+      let status = if !failures.is_empty() {
+        "failed"
+      } else if skipped {
+        "skipped"
+      } else {
+        "passed"
+      };
+      let seconds: f64 = testcase.attribute("time").and_then(|time| time.parse().ok()).unwrap_or(0.0);
+      let case = Case {
+        suite: testcase.attribute("classname").unwrap_or_default().to_string(),
+        name: testcase.attribute("name").unwrap_or_default().to_string(),
+        status: status.to_string(),
+        duration_ms: (seconds * 1000.0).round() as u64,
+        failures,
+        output: String::new(),
+      };
+      cases.push(case);
+    }
+    reports.push((stem, cases));
+  }
+  reports
+}
+
+// Which run of `program` wrote the GoogleTest file `stem`, when it did: 0
+// for `<program>.xml`, then 1 for `<program>_1.xml` and so on.
+fn run_number(stem: &str, program: &str) -> Option<u32> {
+  match stem.strip_prefix(program)? {
+    "" => Some(0),
+    rest => rest.strip_prefix('_').filter(|number| number.chars().all(|c| c.is_ascii_digit()))?.parse().ok(),
+  }
+}
+
+// Puts together ctest's results, its JUnit file when there is one and the
+// GoogleTest reports in `gtest_dir`. A test GoogleTest reported on gets its
+// cases and failure messages, any other is reported as ctest saw it.
+pub fn collect(results: &[ctest::TestResult], commands: &[ctest::TestCommand], junit_file: Option<&str>, gtest_dir: &str) -> Vec<Case> {
+  let details = junit_file.map(read_ctest_junit).unwrap_or_default();
+  let mut gtest_reports = read_gtest_reports(gtest_dir);
+  let mut cases: Vec<Case> = Vec::new();
+  for result in results {
+    let detail = details.iter().find(|detail| detail.name == result.name);
+    let output = output::strip_ansi(detail.map(|detail| detail.output.as_str()).unwrap_or(""));
+
+    // GoogleTest writes its report at the end, a binary that crashed or
+    // timed out has none.
+    let finished = result.status != "crashed" && result.status != "timeout";
+    let program = commands
+      .iter()
+      .find(|command| command.name == result.name)
+      .and_then(|command| Path::new(command.command[0].as_str()).file_name().map(|name| name.to_string_lossy().to_string()));
+    // A test per case, as gtest_discover_tests registers them, or a whole
+    // binary. ctest runs the tests one after another, so the earliest report
+    // of the binary that's left is the one this test wrote.
+    let own: Vec<Case> = if let Some((report, idx)) = gtest_reports.iter().enumerate().find_map(|(report, (_, cases))| {
+      Some((report, cases.iter().position(|case| format!("{}.{}", case.suite, case.name) == result.name)?))
+    }) {
+      let case = gtest_reports[report].1.remove(idx);
+      if gtest_reports[report].1.is_empty() {
+        gtest_reports.remove(report);
+      }
+      vec![case]
+    } else if let Some(program) = program.as_deref().filter(|_| finished) {
+      let earliest =
+        gtest_reports.iter().enumerate().filter_map(|(report, (stem, _))| Some((run_number(stem, program)?, report))).min();
+      match earliest {
+        Some((_, report)) => gtest_reports.remove(report).1,
+        None => Vec::new(),
+      }
+    } else {
+      Vec::new()
+    };
+
+    if own.is_empty() || !finished {
+      let mut failures: Vec<String> = own.into_iter().flat_map(|case| case.failures).collect();
+      if failures.is_empty() && !result.passed() && result.status != "skipped" {
+        failures.push(detail.and_then(|detail| detail.failure.clone()).unwrap_or_else(|| result.status.clone()));
+      }
+      cases.push(Case {
+        suite: String::from("ctest"),
+        name: result.name.clone(),
+        status: result.status.clone(),
+        duration_ms: result.duration_ms,
+        failures,
+        output,
+      });
+      continue;
+    }
+    for mut case in own {
+      case.output = output.clone();
+      cases.push(case);
+    }
+  }
+  cases
+}
+
+pub fn write(report: &Report, cases: &[Case]) -> Result<()> {
+  if let Some(parent) = Path::new(report.path.as_str()).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+    fs::create_dir_all(parent)?;
+  }
+  let contents = match report.format {
+    Format::Junit => junit(cases),
+    Format::Json => json(cases).to_string() + "\n",
+  };
+  fs::write(report.path.as_str(), contents)
+}
+
+fn count(cases: &[&Case], statuses: &[&str]) -> usize {
+  cases.iter().filter(|case| statuses.contains(&case.status.as_str())).count()
+}
+
+fn seconds(duration_ms: u64) -> String {
+  format!("{:.3}", duration_ms as f64 / 1000.0)
+}
+
+// The JUnit XML CI servers read: suites of test cases, with a failure for
+// failed assertions and an error for crashes and timeouts.
+fn junit(cases: &[Case]) -> String {
+  let mut suites: Vec<(&str, Vec<&Case>)> = Vec::new();
+  for case in cases {
+    match suites.iter_mut().find(|(suite, _)| *suite == case.suite) {
+      Some((_, suite_cases)) => suite_cases.push(case),
+      None => suites.push((case.suite.as_str(), vec![case])),
+    }
+  }
+  let all: Vec<&Case> = cases.iter().collect();
+  let total_ms: u64 = cases.iter().map(|case| case.duration_ms).sum();
+  let mut xml = format!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"gojo\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{}\">\n",
+    cases.len(),
+    count(&all, &["failed"]),
+    count(&all, &["crashed", "timeout"]),
+    count(&all, &["skipped"]),
+    seconds(total_ms)
+  );
+  for (suite, suite_cases) in suites.iter() {
+    let suite_ms: u64 = suite_cases.iter().map(|case| case.duration_ms).sum();
+    xml += format!(
+      "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{}\">\n",
+      escape(suite),
+      suite_cases.len(),
+      count(suite_cases, &["failed"]),
+      count(suite_cases, &["crashed", "timeout"]),
+      count(suite_cases, &["skipped"]),
+      seconds(suite_ms)
+    )
+    .as_str();
+    for case in suite_cases {
+      xml += format!(
+        "    <testcase name=\"{}\" classname=\"{}\" time=\"{}\">\n",
+        escape(case.name.as_str()),
+        escape(suite),
+        seconds(case.duration_ms)
+      )
+      .as_str();
+      let element = match case.status.as_str() {
+        "failed" => Some("failure"),
+        "crashed" | "timeout" => Some("error"),
+        _ => None,
+      };
+      if let Some(element) = element {
+        for failure in case.failures.iter() {
+          let message = failure.lines().next().unwrap_or("");
+          xml += format!(
+            "      <{element} message=\"{}\" type=\"{}\">{}</{element}>\n",
+            escape(message),
+            case.status,
+            escape(failure.as_str())
+          )
+          .as_str();
+        }
+      }
+      if case.status == "skipped" {
+        xml += "      <skipped/>\n";
+      }
+      if !case.output.is_empty() {
+        xml += format!("      <system-out>{}</system-out>\n", escape(case.output.as_str())).as_str();
+      }
+      xml += "    </testcase>\n";
+    }
+    xml += "  </testsuite>\n";
+  }
+  xml += "</testsuites>\n";
+  xml
+}
+
+fn json(cases: &[Case]) -> Value {
+  let all: Vec<&Case> = cases.iter().collect();
+  let tests: Vec<Value> = cases
+    .iter()
+    .map(|case| {
+      Value::Object(vec![
+        (String::from("suite"), Value::from(case.suite.as_str())),
+        (String::from("name"), Value::from(case.name.as_str())),
+        (String::from("status"), Value::from(case.status.as_str())),
+        (String::from("duration_ms"), Value::from(case.duration_ms)),
+        (String::from("failures"), Value::from(case.failures.clone())),
+        (String::from("output"), Value::from(case.output.as_str())),
+      ])
+    })
+    .collect();
+  Value::Object(vec![
+    (String::from("passed"), Value::from(count(&all, &["passed"]))),
+    (String::from("failed"), Value::from(count(&all, &["failed", "crashed", "timeout"]))),
+    (String::from("skipped"), Value::from(count(&all, &["skipped"]))),
+    (String::from("duration_ms"), Value::from(cases.iter().map(|case| case.duration_ms).sum::<u64>())),
+    (String::from("tests"), Value::Array(tests)),
+  ])
+}
+
+// Escapes text for XML, dropping the control characters XML can't hold.
+fn escape(text: &str) -> String {
+  text
+    .chars()
+    .filter(|c| !c.is_control() || matches!(c, '\n' | '\t' | '\r'))
+    .collect::<String>()
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const GTEST: &str = include_str!("../tests/data/gtest-report.xml");
+
+  fn result(name: &str, status: &str) -> ctest::TestResult {
+    ctest::TestResult { name: name.to_string(), status: status.to_string(), duration_ms: 5 }
+  }
+
+  fn command(name: &str, program: &str) -> ctest::TestCommand {
+    ctest::TestCommand { name: name.to_string(), command: vec![format!("/build/{program}")], cwd: String::from("/build") }
+  }
+
+  // A GoogleTest report with one case of suite `Table`.
+  fn gtest_report(case: &str, failure: Option<&str>) -> String {
+    let failure = failure.map(|failure| format!("<failure message=\"{failure}\" type=\"\"><![CDATA[{failure}]]></failure>"));
+    format!(
+      "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites tests=\"1\" name=\"AllTests\">\n  <testsuite name=\"Table\" tests=\"1\">\n    <testcase name=\"{case}\" status=\"run\" result=\"completed\" time=\"0.002\" classname=\"Table\">{}</testcase>\n  </testsuite>\n</testsuites>\n",
+      failure.unwrap_or_default()
+    )
+  }
+
+  fn report_dir(name: &str, files: &[(&str, String)]) -> String {
+    let dir = std::env::temp_dir().join(format!("gojo-testreport-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (file, text) in files {
+      fs::write(dir.join(file), text).unwrap();
+    }
+    dir.to_string_lossy().to_string()
+  }
+
+  fn summary(cases: &[Case]) -> Vec<(String, String, String)> {
+    cases.iter().map(|case| (case.suite.clone(), case.name.clone(), case.status.clone())).collect()
+  }
+
+  fn case(suite: &str, name: &str, status: &str) -> (String, String, String) {
+    (suite.to_string(), name.to_string(), status.to_string())
+  }
+
+  #[test]
+  fn reports_discovered_cases() {
+    // gtest_discover_tests runs the binary once per case.
+    let dir = report_dir(
+      "discovered",
+      &[("unit_tests.xml", gtest_report("Grows", None)), ("unit_tests_1.xml", gtest_report("Shrinks", Some("table.cc:9\nExpected: 0")))],
+    );
+    let results = [result("Table.Grows", "passed"), result("Table.Shrinks", "failed")];
+    let commands = [command("Table.Grows", "unit_tests"), command("Table.Shrinks", "unit_tests")];
+    let cases = collect(&results, &commands, None, dir.as_str());
+    let _ = fs::remove_dir_all(dir.as_str());
+
+    assert_eq!(summary(&cases), [case("Table", "Grows", "passed"), case("Table", "Shrinks", "failed")]);
+    assert_eq!(cases[0].duration_ms, 2);
+    assert_eq!(cases[1].failures, ["table.cc:9\nExpected: 0"]);
+  }
+
+  #[test]
+  fn splits_runs_of_one_binary() {
+    // Three tests run the same binary with different filters, the first
+    // matching no cases at all.
+    let dir = report_dir(
+      "runs",
+      &[
+        ("unit_tests.xml", String::from("<testsuites tests=\"0\" name=\"AllTests\">\n</testsuites>\n")),
+        ("unit_tests_1.xml", gtest_report("Grows", None)),
+        ("unit_tests_2.xml", GTEST.to_string()),
+      ],
+    );
+    let results = [result("none", "passed"), result("table", "passed"), result("all", "failed")];
+    let commands = [command("none", "unit_tests"), command("table", "unit_tests"), command("all", "unit_tests")];
+    let cases = collect(&results, &commands, None, dir.as_str());
+    let _ = fs::remove_dir_all(dir.as_str());
+
+    assert_eq!(
+      summary(&cases),
+      [
+        case("ctest", "none", "passed"),
+        case("Table", "Grows", "passed"),
+        case("Parser", "ParsesNumbers", "passed"),
+        case("Parser", "RejectsGarbage", "failed"),
+        case("Sizes/TableTest", "Grows/0", "passed"),
+      ]
+    );
+    assert!(cases[3].failures[0].contains("parse(\"x<1\")"));
+  }
+
+  #[test]
+  fn numbers_runs_past_ten() {
+    assert_eq!(run_number("unit_tests", "unit_tests"), Some(0));
+    assert_eq!(run_number("unit_tests_10", "unit_tests"), Some(10));
+    assert_eq!(run_number("unit_tests_fast", "unit_tests"), None);
+    assert_eq!(run_number("unit", "unit_tests"), None);
+  }
+
+  #[test]
+  fn falls_back_to_ctest_for_crashes_and_timeouts() {
+    // Neither killed binary wrote a report, the file is the last test's.
+    let dir = report_dir("killed", &[("unit_tests.xml", GTEST.to_string())]);
+    let junit = format!("{dir}/ctest.xml");
+    fs::write(
+      junit.as_str(),
+      "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuite name=\"Linux-c++\" tests=\"3\" failures=\"2\">\n\t<testcase name=\"crash\" classname=\"crash\" time=\"0.1\" status=\"fail\">\n\t\t<failure message=\"SegFault\"/>\n\t\t<system-out>[ RUN      ] Crash.NullPointer\n</system-out>\n\t</testcase>\n\t<testcase name=\"slow\" classname=\"slow\" time=\"1.5\" status=\"fail\">\n\t\t<system-out></system-out>\n\t</testcase>\n</testsuite>\n",
+    )
+    .unwrap();
+    let results = [result("crash", "crashed"), result("slow", "timeout"), result("unit", "failed")];
+    let commands = [command("crash", "crasher"), command("slow", "unit_tests"), command("unit", "unit_tests")];
+    let cases = collect(&results, &commands, Some(junit.as_str()), dir.as_str());
+    let _ = fs::remove_dir_all(dir.as_str());
+
+    assert_eq!(summary(&cases)[..2], [case("ctest", "crash", "crashed"), case("ctest", "slow", "timeout")]);
+    assert_eq!(cases[0].failures, ["SegFault"]);
+    assert_eq!(cases[0].output, "[ RUN      ] Crash.NullPointer\n");
+    assert_eq!(cases[1].failures, ["timeout"]);
+    assert_eq!(cases.len(), 5);
+    assert_eq!(summary(&cases)[3], case("Parser", "RejectsGarbage", "failed"));
+  }
+}
@@ -0,0 +1,401 @@
+use crate::json;
+use crate::json::Value;
+use crate::output::Event;
+use crate::error::GojoError;
+
+use std::collections::hash_map::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Result;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::vec::Vec;
+
+const TOP_N: usize = 10;
+
+// One build step. Times are milliseconds since the start of the build when
+// they come from Ninja, and zero based durations for time traces.
+struct Step {
+  output: String,
+  start_ms: u64,
+  end_ms: u64,
+}
+
+impl Step {
+  fn duration_ms(&self) -> u64 {
+    self.end_ms - self.start_ms
+  }
+
+  fn is_compile(&self) -> bool {
+    self.output.ends_with(".o") || self.output.ends_with(".obj")
+  }
+}
+
+struct Header {
+  path: String,
+  total_ms: u64,
+  count: usize,
+}
+
+struct Report {
+  source: &'static str,
+  units: Vec<Step>,
+  headers: Vec<Header>,
+  critical_path: Vec<Step>,
+}
+
+// The build directory before a build, so the report covers only what that
+// build did.
+pub struct Snapshot {
+  ninja_log: Option<Vec<u8>>,
+  started: SystemTime,
+}
+
+pub fn snapshot(build_dir: &str) -> Snapshot {
+  Snapshot {
+    ninja_log: fs::read(format!("{build_dir}/.ninja_log")).ok(),
+    started: SystemTime::now(),
+  }
+}
+
+// Collects per translation unit compile times for the build that ran since
+// `before` and writes `gojo-timings.json` and `gojo-timings.html` next to it.
+// Ninja's log provides wall-clock times and the critical path, Clang's
+// `-ftime-trace` output adds the cost of each included header.
+pub fn report(build_dir: &str, before: &Snapshot) -> Result<()> {
+  let ninja_steps = read_ninja_log(build_dir, before);
+  let traces = match ninja_steps.as_ref() {
+    Some(steps) => read_time_traces(build_dir, steps),
+    None => find_time_traces(Path::new(build_dir), before.started),
+  };
+
+  let (source, units, critical_path) = match (ninja_steps, traces.is_empty()) {
+    (Some(steps), _) if steps.is_empty() => {
+      status!("\x1b[1;35mNo timings\x1b[0m nothing was rebuilt\n\n");
+      return Ok(());
+    }
+    (Some(steps), _) => {
+      let critical_path = critical_path(&steps);
+      let units = steps.into_iter().filter(|step| step.is_compile()).collect();
+      ("ninja", units, critical_path)
+    }
+    (None, false) => {
+      let units = traces
+        .iter()
+        .map(|trace| Step {
+          output: trace.unit.clone(),
+          start_ms: 0,
+          end_ms: trace.total_ms,
+        })
+        .collect();
+      ("time-trace", units, Vec::new())
+    }
+    (None, true) => {
+      return Err(GojoError::Failed(
+        "\x1b[31mno timing data found:\x1b[0m\n\tbuild timings need the Ninja generator (CMAKE_GENERATOR=Ninja) or a Clang compiler\n".to_string(),
+      ).into());
+    }
+  };
+
+  let mut headers: HashMap<String, Header> = HashMap::new();
+  for trace in traces.iter() {
+    for (path, ms) in trace.headers.iter() {
+      let header = headers.entry(path.clone()).or_insert(Header {
+        path: path.clone(),
+        total_ms: 0,
+        count: 0,
+      });
+      header.total_ms += ms;
+      header.count += 1;
+    }
+  }
+
+  let mut report = Report {
+    source,
+    units,
+    headers: headers.into_values().collect(),
+    critical_path,
+  };
+  report.units.sort_by_key(|unit| std::cmp::Reverse(unit.duration_ms()));
+  report.headers.sort_by_key(|header| std::cmp::Reverse(header.total_ms));
+
+  let json_path = format!("{build_dir}/gojo-timings.json");
+  let html_path = format!("{build_dir}/gojo-timings.html");
+  fs::write(json_path.as_str(), to_json(&report).to_string())?;
+  fs::write(html_path.as_str(), to_html(&report))?;
+
+  print_summary(&report);
+  status!("\x1b[1;35mTimings written to\x1b[0m {}\n\n", html_path);
+  Event::new("timings-report")
+    .with("source", source)
+    .with("json", json_path)
+    .with("html", html_path)
+    .emit();
+  Ok(())
+}
+
+// Reads the steps of the Ninja run since `before`: the lines it appended to
+// the log. When the log grows too long Ninja recompacts it, rewriting the
+// older entries, and then the run's steps are the lines that are new.
+fn read_ninja_log(build_dir: &str, before: &Snapshot) -> Option<Vec<Step>> {
+  let contents = fs::read(format!("{build_dir}/.ninja_log")).ok()?;
+  let lines: Vec<&str> = match before.ninja_log.as_deref() {
+    Some(old) if contents.starts_with(old) => std::str::from_utf8(&contents[old.len()..]).ok()?.lines().collect(),
+    Some(old) => {
+      let old: HashSet<&str> = std::str::from_utf8(old).ok()?.lines().collect();
+      std::str::from_utf8(&contents).ok()?.lines().filter(|line| !old.contains(line)).collect()
+    }
+    None => std::str::from_utf8(&contents).ok()?.lines().collect(),
+  };
+
+  let mut steps: Vec<Step> = Vec::new();
+  for line in lines {
+    if line.starts_with('#') {
+      continue;
+    }
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 4 {
+      continue;
+    }
+    let (Ok(start_ms), Ok(end_ms)) = (fields[0].parse::<u64>(), fields[1].parse::<u64>()) else {
+      continue;
+    };
+    steps.push(Step {
+      output: fields[3].to_string(),
+      start_ms,
+      end_ms,
+    });
+  }
+  Some(steps)
+}
+
+// Walks back from the last step to finish, each time picking the step that
+// finished most recently before the current one started. Without the full
+// dependency graph this is an approximation, but the steps it picks are the
+// ones the build was waiting on.
+fn critical_path(steps: &[Step]) -> Vec<Step> {
+  let mut path: Vec<Step> = Vec::new();
+  let mut current = steps.iter().max_by_key(|step| step.end_ms);
+  while let Some(step) = current {
+    path.push(Step {
+      output: step.output.clone(),
+      start_ms: step.start_ms,
+      end_ms: step.end_ms,
+    });
+    current = steps
+      .iter()
+      .filter(|other| other.end_ms <= step.start_ms && other.end_ms < step.end_ms)
+      .max_by_key(|other| other.end_ms);
+  }
+  path.reverse();
+  path
+}
+
+struct Trace {
+  unit: String,
+  total_ms: u64,
+  headers: Vec<(String, u64)>,
+}
+
+// The `-ftime-trace` JSON Clang wrote next to each object Ninja compiled,
+// e.g. main.cc.json for main.cc.o.
+fn read_time_traces(build_dir: &str, steps: &[Step]) -> Vec<Trace> {
+  steps
+    .iter()
+    .filter(|step| step.is_compile())
+    .filter_map(|step| {
+      let stem = step.output.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(step.output.as_str());
+      read_time_trace(Path::new(build_dir).join(format!("{stem}.json")).as_path(), step.output.as_str())
+    })
+    .collect()
+}
+
+// Without Ninja's log, the traces under a target's `.dir/` that were written
+// since the build started.
+fn find_time_traces(dir: &Path, since: SystemTime) -> Vec<Trace> {
+  let mut files: Vec<PathBuf> = Vec::new();
+  collect_trace_files(dir, since, &mut files);
+  files
+    .iter()
+    .filter_map(|file| {
+      let unit = file.to_string_lossy().trim_end_matches(".json").to_string() + ".o";
+      read_time_trace(file.as_path(), unit.as_str())
+    })
+    .collect()
+}
+
+// None when `file` is missing or isn't a time trace.
+fn read_time_trace(file: &Path, unit: &str) -> Option<Trace> {
+  let contents = fs::read_to_string(file).ok()?;
+  let value = json::parse(contents.as_str())?;
+  let events = value.get("traceEvents").and_then(|events| events.as_array())?;
+
+  let mut total_ms = 0;
+  let mut headers: Vec<(String, u64)> = Vec::new();
+  for event in events {
+    let name = event.get("name").and_then(|name| name.as_str()).unwrap_or("");
+    let dur_ms = event.get("dur").and_then(|dur| dur.as_f64()).unwrap_or(0.0) as u64 / 1000;
+    if name == "Total ExecuteCompiler" {
+      total_ms = dur_ms;
+    } else if name == "Source" {
+      let detail = event.get("args").and_then(|args| args.get("detail")).and_then(|d| d.as_str());
+      if let Some(detail) = detail {
+        headers.push((detail.to_string(), dur_ms));
+      }
+    }
+  }
+  Some(Trace {
+    unit: unit.to_string(),
+    total_ms,
+    headers,
+  })
+}
+
+fn collect_trace_files(dir: &Path, since: SystemTime, files: &mut Vec<PathBuf>) {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if path.is_dir() {
+      collect_trace_files(path.as_path(), since, files);
+    } else {
+      let name = entry.file_name().to_string_lossy().to_string();
+      let fresh = entry.metadata().and_then(|m| m.modified()).map(|modified| modified >= since).unwrap_or(false);
+      // Traces are named after the object file, e.g. main.cc.json.
+      if fresh && name.ends_with(".json") && path.to_string_lossy().contains(".dir/") {
+        files.push(path);
+      }
+    }
+  }
+}
+
+fn print_summary(report: &Report) {
+  status!("\n\x1b[1;35mSlowest translation units\x1b[0m ({})\n", report.source);
+  for unit in report.units.iter().take(TOP_N) {
+    status!("  {:>8.2}s  {}\n", unit.duration_ms() as f64 / 1000.0, unit.output);
+  }
+
+  if !report.headers.is_empty() {
+    status!("\n\x1b[1;35mMost expensive headers\x1b[0m (inclusive, summed over all units)\n");
+    for header in report.headers.iter().take(TOP_N) {
+      status!(
+        "  {:>8.2}s  {} (included {}x)\n",
+        header.total_ms as f64 / 1000.0,
+        header.path,
+        header.count
+      );
+    }
+  }
+
+  if !report.critical_path.is_empty() {
+    let start = report.critical_path.first().unwrap().start_ms;
+    let end = report.critical_path.last().unwrap().end_ms;
+    status!(
+      "\n\x1b[1;35mCritical path\x1b[0m ({:.2}s)\n",
+      (end - start) as f64 / 1000.0
+    );
+    for step in report.critical_path.iter() {
+      status!("  {:>8.2}s  {}\n", step.duration_ms() as f64 / 1000.0, step.output);
+    }
+  }
+  status!("\n");
+}
+
+fn to_json(report: &Report) -> Value {
+  let step = |step: &Step| {
+    Value::Object(vec![
+      (String::from("output"), Value::from(step.output.as_str())),
+      (String::from("start_ms"), Value::from(step.start_ms)),
+      (String::from("duration_ms"), Value::from(step.duration_ms())),
+    ])
+  };
+  Value::Object(vec![
+    (String::from("source"), Value::from(report.source)),
+    (String::from("units"), Value::Array(report.units.iter().map(step).collect())),
+    (
+      String::from("headers"),
+      Value::Array(
+        report
+          .headers
+          .iter()
+          .map(|header| {
+            Value::Object(vec![
+              (String::from("path"), Value::from(header.path.as_str())),
+              (String::from("total_ms"), Value::from(header.total_ms)),
+              (String::from("count"), Value::from(header.count)),
+            ])
+          })
+          .collect(),
+      ),
+    ),
+    (
+      String::from("critical_path"),
+      Value::Array(report.critical_path.iter().map(step).collect()),
+    ),
+  ])
+}
+
+fn to_html(report: &Report) -> String {
+  let mut html = String::from(
+    "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>gojo build timings</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; width: 100%; margin-bottom: 2em; }
+td, th { text-align: left; padding: 2px 8px; font-size: 13px; }
+td.time { text-align: right; white-space: nowrap; width: 6em; }
+.bar { background: #a24bd1; height: 12px; }
+</style>
+</head>
+<body>
+",
+  );
+  html += format!("<h1>Build timings</h1>\n<p>Source: {}</p>\n", report.source).as_str();
+
+  let longest_unit = report.units.first().map(|unit| unit.duration_ms()).unwrap_or(1).max(1);
+  html += "<h2>Translation units</h2>\n<table>\n<tr><th>Time</th><th>Object</th><th></th></tr>\n";
+  for unit in report.units.iter() {
+    html += html_row(unit.duration_ms(), unit.output.as_str(), longest_unit).as_str();
+  }
+  html += "</table>\n";
+
+  if !report.headers.is_empty() {
+    let longest_header = report.headers[0].total_ms.max(1);
+    html += "<h2>Headers (inclusive parse time, summed)</h2>\n<table>\n<tr><th>Time</th><th>Header</th><th></th></tr>\n";
+    for header in report.headers.iter() {
+      let label = format!("{} ({}x)", header.path, header.count);
+      html += html_row(header.total_ms, label.as_str(), longest_header).as_str();
+    }
+    html += "</table>\n";
+  }
+
+  if !report.critical_path.is_empty() {
+    let longest_step = report.critical_path.iter().map(|step| step.duration_ms()).max().unwrap_or(1).max(1);
+    html += "<h2>Critical path</h2>\n<table>\n<tr><th>Time</th><th>Step</th><th></th></tr>\n";
+    for step in report.critical_path.iter() {
+      html += html_row(step.duration_ms(), step.output.as_str(), longest_step).as_str();
+    }
+    html += "</table>\n";
+  }
+
+  html += "</body>\n</html>\n";
+  html
+}
+
+fn html_row(ms: u64, label: &str, longest: u64) -> String {
+  format!(
+    "<tr><td class=\"time\">{:.2}s</td><td>{}</td><td style=\"width:40%\"><div class=\"bar\" style=\"width:{:.1}%\"></div></td></tr>\n",
+    ms as f64 / 1000.0,
+    escape_html(label),
+    ms as f64 * 100.0 / longest as f64
+  )
+}
+
+fn escape_html(text: &str) -> String {
+  text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
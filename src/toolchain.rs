@@ -0,0 +1,44 @@
+use crate::error::GojoError;
+
+use std::env;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+// The C++ compiler CMake will pick up, following the same `CXX` override.
+pub fn cxx_compiler() -> String {
+  match std::env::var("CXX") {
+    Ok(cxx) if !cxx.is_empty() => cxx,
+    _ => String::from("c++"),
+  }
+}
+
+pub fn is_clang() -> bool {
+  let output = Command::new(cxx_compiler())
+    .arg("--version")
+    .stdin(Stdio::null())
+    .stderr(Stdio::null())
+    .output();
+  match output {
+    Ok(output) => String::from_utf8_lossy(&output.stdout).contains("clang"),
+    Err(_) => false,
+  }
+}
+
+// Where `program` would be found on the PATH.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+  env::var_os("PATH").and_then(|paths| env::split_paths(&paths).map(|dir| dir.join(program)).find(|path| path.is_file()))
+}
+
+// Turns the error from spawning `tool` into a readable one when the tool
+// isn't installed.
+pub fn spawn_error(tool: &str, error: Error) -> Error {
+  if error.kind() == ErrorKind::NotFound {
+    return GojoError::ToolMissing(
+      format!("\x1b[31mtool not found:\x1b[0m\n\t'{tool}' is not installed or not on your PATH\n"),
+    ).into();
+  }
+  error
+}
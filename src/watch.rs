@@ -0,0 +1,354 @@
+use std::collections::hash_map::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::os::unix::process::CommandExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Child;
+use std::process::Command;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time;
+use std::vec::Vec;
+
+extern crate libc;
+
+// How long the tree has to be quiet before a burst of edits (an editor's
+// save, a git checkout) counts as finished.
+const DEBOUNCE: time::Duration = time::Duration::from_millis(200);
+const POLL_INTERVAL_MS: i32 = 100;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+// Whether gojo is the terminal's foreground process group, and so hands the
+// terminal to each run.
+static OWNS_TERMINAL: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+  INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+pub struct Watcher {
+  fd: libc::c_int,
+  dirs: HashMap<libc::c_int, PathBuf>,
+  // The files whose changes rerun the command.
+  files: Vec<PathBuf>,
+  // Searched like `collect_src_files` does, so sources added there are
+  // watched from then on.
+  source_dirs: Vec<PathBuf>,
+  // Never watched, whether or not it exists yet.
+  build_dir: PathBuf,
+  cpp: String,
+  hpp: String,
+}
+
+impl Drop for Watcher {
+  fn drop(&mut self) {
+    unsafe {
+      libc::close(self.fd);
+    }
+  }
+}
+
+impl Watcher {
+  pub fn new(files: Vec<PathBuf>, source_dirs: Vec<PathBuf>, build_dir: &str, cpp: &str, hpp: &str) -> Result<Watcher> {
+    let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+    if fd < 0 {
+      return Err(Error::last_os_error());
+    }
+    let mut watcher = Watcher {
+      fd,
+      dirs: HashMap::new(),
+      files,
+      source_dirs: source_dirs.clone(),
+      build_dir: PathBuf::from(build_dir),
+      cpp: cpp.to_string(),
+      hpp: hpp.to_string(),
+    };
+    // Files are watched through their directories, editors often save by
+    // replacing the file.
+    for dir in source_dirs {
+      watcher.add_tree(dir.as_path(), &mut Vec::new())?;
+    }
+    let parents: Vec<PathBuf> = watcher.files.iter().filter_map(|file| file.parent()).map(Path::to_path_buf).collect();
+    for dir in parents {
+      watcher.add_dir(dir.as_path())?;
+    }
+    Ok(watcher)
+  }
+
+  pub fn file_count(&self) -> usize {
+    self.files.len()
+  }
+
+  // A directory that is already gone is skipped, editors create and remove
+  // temporary ones all the time.
+  fn add_dir(&mut self, dir: &Path) -> Result<bool> {
+    let path = CString::new(dir.to_string_lossy().as_bytes()).unwrap();
+    let mask = libc::IN_CLOSE_WRITE
+      | libc::IN_MODIFY
+      | libc::IN_CREATE
+      | libc::IN_DELETE
+      | libc::IN_MOVED_FROM
+      | libc::IN_MOVED_TO;
+    let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), mask) };
+    if wd < 0 {
+      let error = Error::last_os_error();
+      if error.kind() == ErrorKind::NotFound {
+        return Ok(false);
+      }
+      return Err(error);
+    }
+    self.dirs.insert(wd, dir.to_path_buf());
+    Ok(true)
+  }
+
+  // Watches `dir` and everything below it, collecting the source files
+  // found there.
+  fn add_tree(&mut self, dir: &Path, sources: &mut Vec<PathBuf>) -> Result<()> {
+    if dir.starts_with(self.build_dir.as_path()) || !self.add_dir(dir)? {
+      return Ok(());
+    }
+    let entries = match fs::read_dir(dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+      Err(e) => return Err(e),
+    };
+    for entry in entries.flatten() {
+      let path = entry.path();
+      if path.is_dir() {
+        self.add_tree(path.as_path(), sources)?;
+      } else if self.is_source_file(entry.file_name().to_string_lossy().as_ref()) {
+        sources.push(path);
+      }
+    }
+    Ok(())
+  }
+
+  fn in_source_dir(&self, path: &Path) -> bool {
+    !path.starts_with(self.build_dir.as_path()) && self.source_dirs.iter().any(|dir| path.starts_with(dir))
+  }
+
+  // The same test `collect_src_files` applies.
+  fn is_source_file(&self, name: &str) -> bool {
+    name.ends_with(self.cpp.as_str()) || name.ends_with(self.hpp.as_str())
+  }
+
+  // Waits up to `timeout_ms` (forever when negative) and returns the watched
+  // files that changed. Directories created meanwhile are watched too.
+  fn poll(&mut self, timeout_ms: i32) -> Vec<PathBuf> {
+    let mut pollfd = libc::pollfd {
+      fd: self.fd,
+      events: libc::POLLIN,
+      revents: 0,
+    };
+    let ready = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+    if ready <= 0 {
+      return Vec::new();
+    }
+
+    let mut changed: Vec<PathBuf> = Vec::new();
+    let mut buffer = [0u8; 4096];
+    loop {
+      let len = unsafe { libc::read(self.fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+      if len <= 0 {
+        break;
+      }
+
+      let mut offset = 0;
+      let header = std::mem::size_of::<libc::inotify_event>();
+      while offset + header <= len as usize {
+        let event = unsafe { std::ptr::read_unaligned(buffer.as_ptr().add(offset) as *const libc::inotify_event) };
+        let name_bytes = &buffer[offset + header..offset + header + event.len as usize];
+        let name = String::from_utf8_lossy(name_bytes).trim_end_matches('\0').to_string();
+        offset += header + event.len as usize;
+
+        // The directory is gone, and its watch with it.
+        if event.mask & libc::IN_IGNORED != 0 {
+          self.dirs.remove(&event.wd);
+          continue;
+        }
+        let Some(dir) = self.dirs.get(&event.wd).cloned() else {
+          continue;
+        };
+        let path = dir.join(name.as_str());
+        let mut touched: Vec<PathBuf> = Vec::new();
+        if event.mask & libc::IN_ISDIR != 0 {
+          if event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 && self.in_source_dir(path.as_path()) {
+            let _ = self.add_tree(path.as_path(), &mut touched);
+          }
+        } else if self.files.contains(&path) || (self.in_source_dir(path.as_path()) && self.is_source_file(name.as_str())) {
+          touched.push(path);
+        }
+
+        for path in touched {
+          let removed = event.mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0;
+          if removed && self.in_source_dir(path.as_path()) {
+            self.files.retain(|file| *file != path);
+          } else if !removed && !self.files.contains(&path) {
+            self.files.push(path.clone());
+          }
+          if !changed.contains(&path) {
+            changed.push(path);
+          }
+        }
+      }
+    }
+    changed
+  }
+
+  // Blocks for the first change, then keeps collecting until the tree has
+  // been quiet for `DEBOUNCE`.
+  fn wait_for_changes(&mut self, first_timeout_ms: i32) -> Vec<PathBuf> {
+    let mut changed = self.poll(first_timeout_ms);
+    if changed.is_empty() {
+      return changed;
+    }
+    let mut quiet_since = time::Instant::now();
+    while quiet_since.elapsed() < DEBOUNCE {
+      let more = self.poll(DEBOUNCE.as_millis() as i32);
+      if !more.is_empty() {
+        quiet_since = time::Instant::now();
+        for path in more {
+          if !changed.contains(&path) {
+            changed.push(path);
+          }
+        }
+      }
+    }
+    changed
+  }
+}
+
+// Reruns `gojo <command> <args>` whenever one of the watched files changes,
+// cancelling a run that is still in flight.
+pub fn watch(command: &str, args: &[String], project_root: &str, mut watcher: Watcher) -> Result<()> {
+
+  unsafe {
+    libc::signal(libc::SIGINT, on_interrupt as *const () as libc::sighandler_t);
+    libc::signal(libc::SIGTERM, on_interrupt as *const () as libc::sighandler_t);
+    let foreground = libc::isatty(libc::STDIN_FILENO) == 1 && libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp();
+    OWNS_TERMINAL.store(foreground, Ordering::SeqCst);
+  }
+
+  let exe = std::env::current_exe()?;
+  let mut reason = String::from("starting");
+  loop {
+    // Clear the screen and scrollback so every run starts from the top.
+    status!("\x1b[2J\x1b[3J\x1b[H");
+    let command_line = format!("gojo {} {}", command, args.join(" "));
+    status!("\x1b[1;35m[watch]\x1b[0m {} \x1b[0;35m({})\x1b[0m\n\n", command_line.trim_end(), reason);
+
+    let start = time::Instant::now();
+    let mut child = spawn(&exe, command, args)?;
+    let mut cancelled_by: Vec<PathBuf> = Vec::new();
+    let success = loop {
+      if INTERRUPTED.load(Ordering::SeqCst) {
+        kill(&mut child);
+        return Ok(());
+      }
+      if let Some(status) = child.try_wait()? {
+        take_terminal();
+        // Ctrl-C goes to the child while it has the terminal. `gojo run`
+        // passes on the exit code of a program that Ctrl-C stopped.
+        if status.signal() == Some(libc::SIGINT) || status.code() == Some(128 + libc::SIGINT) {
+          return Ok(());
+        }
+        break status.success();
+      }
+      let changed = watcher.wait_for_changes(POLL_INTERVAL_MS);
+      if !changed.is_empty() {
+        kill(&mut child);
+        cancelled_by = changed;
+        break false;
+      }
+    };
+
+    if !cancelled_by.is_empty() {
+      reason = describe(&cancelled_by, project_root, "cancelled, ");
+      continue;
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    if success {
+      status!(
+        "\n\x1b[1;35m[watch]\x1b[0m \x1b[1;32m{} succeeded\x1b[0m in {:.1}s, watching {} files...\n",
+        command,
+        elapsed,
+        watcher.file_count()
+      );
+    } else {
+      status!(
+        "\n\x1b[1;35m[watch]\x1b[0m \x1b[1;31m{} failed\x1b[0m after {:.1}s, watching {} files...\n",
+        command,
+        elapsed,
+        watcher.file_count()
+      );
+    }
+
+    let changed = loop {
+      if INTERRUPTED.load(Ordering::SeqCst) {
+        return Ok(());
+      }
+      let changed = watcher.wait_for_changes(POLL_INTERVAL_MS);
+      if !changed.is_empty() {
+        break changed;
+      }
+    };
+    reason = describe(&changed, project_root, "");
+  }
+}
+
+fn spawn(exe: &Path, command: &str, args: &[String]) -> Result<Child> {
+  let mut cmd = Command::new(exe);
+  cmd.arg(command);
+  if crate::output::is_json() {
+    cmd.args(["--message-format", "json"]);
+  }
+  cmd.args(args);
+  // A process group of its own lets a cancel reach cmake, make and every
+  // compiler they started. It gets the terminal as well, a background group
+  // reading from it, like a program under `gojo run`, would be stopped.
+  let child = cmd.process_group(0).spawn()?;
+  give_terminal(child.id() as libc::pid_t);
+  Ok(child)
+}
+
+fn kill(child: &mut Child) {
+  let pgid = child.id() as libc::pid_t;
+  unsafe {
+    libc::kill(-pgid, libc::SIGTERM);
+  }
+  let _ = child.wait();
+  take_terminal();
+}
+
+// Makes `pgid` the terminal's foreground process group, when gojo started
+// in the foreground of one.
+fn give_terminal(pgid: libc::pid_t) {
+  if !OWNS_TERMINAL.load(Ordering::SeqCst) {
+    return;
+  }
+  unsafe {
+    // Changing the foreground group from the background raises SIGTTOU.
+    let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+    libc::tcsetpgrp(libc::STDIN_FILENO, pgid);
+    libc::signal(libc::SIGTTOU, previous);
+  }
+}
+
+fn take_terminal() {
+  give_terminal(unsafe { libc::getpgrp() });
+}
+
+fn describe(changed: &[PathBuf], project_root: &str, prefix: &str) -> String {
+  let first = changed[0].to_string_lossy().to_string();
+  let first = first.strip_prefix(project_root).unwrap_or(first.as_str()).trim_start_matches('/').to_string();
+  if changed.len() == 1 {
+    return format!("{prefix}{first} changed");
+  }
+  format!("{prefix}{first} and {} more changed", changed.len() - 1)
+}
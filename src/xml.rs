@@ -0,0 +1,256 @@
+use std::vec::Vec;
+
+// Just enough XML for the reports tools write, like Valgrind's and
+// GoogleTest's: elements, attributes, text and the predefined entities.
+// Comments, processing instructions and doctypes are skipped.
+pub struct Element {
+  pub name: String,
+  pub attributes: Vec<(String, String)>,
+  pub children: Vec<Element>,
+  // The text directly inside the element, CDATA included.
+  pub text: String,
+}
+
+impl Element {
+  pub fn child(&self, name: &str) -> Option<&Element> {
+    self.children.iter().find(|child| child.name == name)
+  }
+
+  pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+    self.children.iter().filter(move |child| child.name == name)
+  }
+
+  pub fn attribute(&self, name: &str) -> Option<&str> {
+    self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+  }
+
+  // The text of the named child, trimmed.
+  pub fn child_text(&self, name: &str) -> Option<&str> {
+    self.child(name).map(|child| child.text.trim())
+  }
+}
+
+pub fn parse(text: &str) -> Option<Element> {
+  let mut parser = Parser { text, pos: 0 };
+  parser.skip_misc();
+  let root = parser.element()?;
+  parser.skip_misc();
+  if parser.pos != text.len() {
+    return None;
+  }
+  Some(root)
+}
+
+struct Parser<'a> {
+  text: &'a str,
+  pos: usize,
+}
+
+impl Parser<'_> {
+  fn rest(&self) -> &str {
+    &self.text[self.pos..]
+  }
+
+  fn skip_whitespace(&mut self) {
+    let trimmed = self.rest().trim_start();
+    self.pos = self.text.len() - trimmed.len();
+  }
+
+  fn expect(&mut self, literal: &str) -> Option<()> {
+    if self.rest().starts_with(literal) {
+      self.pos += literal.len();
+      return Some(());
+    }
+    None
+  }
+
+  // Moves past `end`, returning what came before it.
+  fn until(&mut self, end: &str) -> Option<&str> {
+    let start = self.pos;
+    let idx = self.rest().find(end)?;
+    self.pos += idx + end.len();
+    Some(&self.text[start..start + idx])
+  }
+
+  // Whitespace, comments, `<?...?>` and `<!DOCTYPE ...>` between elements.
+  fn skip_misc(&mut self) {
+    loop {
+      self.skip_whitespace();
+      let skipped = if self.rest().starts_with("<!--") {
+        self.until("-->")
+      } else if self.rest().starts_with("<?") {
+        self.until("?>")
+      } else if self.rest().starts_with("<!DOCTYPE") {
+        self.until(">")
+      } else {
+        return;
+      };
+      if skipped.is_none() {
+        self.pos = self.text.len();
+        return;
+      }
+    }
+  }
+
+  fn name(&mut self) -> Option<String> {
+    let end = self.rest().find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '='))?;
+    if end == 0 {
+      return None;
+    }
+    let name = self.rest()[..end].to_string();
+    self.pos += end;
+    Some(name)
+  }
+
+  fn element(&mut self) -> Option<Element> {
+    self.expect("<")?;
+    let name = self.name()?;
+    let mut element = Element { name, attributes: Vec::new(), children: Vec::new(), text: String::new() };
+    loop {
+      self.skip_whitespace();
+      if self.expect("/>").is_some() {
+        return Some(element);
+      }
+      if self.expect(">").is_some() {
+        break;
+      }
+      let key = self.name()?;
+      self.skip_whitespace();
+      self.expect("=")?;
+      self.skip_whitespace();
+      let quote = if self.rest().starts_with('"') { "\"" } else { "'" };
+      self.expect(quote)?;
+      let value = unescape(self.until(quote)?);
+      element.attributes.push((key, value));
+    }
+
+    loop {
+      if self.rest().starts_with("</") {
+        self.expect("</")?;
+        if self.until(">")?.trim_end() != element.name {
+          return None;
+        }
+        return Some(element);
+      } else if self.rest().starts_with("<![CDATA[") {
+        self.expect("<![CDATA[")?;
+        let data = self.until("]]>")?.to_string();
+        element.text += data.as_str();
+      } else if self.rest().starts_with("<!--") || self.rest().starts_with("<?") {
+        self.skip_misc();
+      } else if self.rest().starts_with('<') {
+        element.children.push(self.element()?);
+      } else {
+        let end = self.rest().find('<')?;
+        let text = unescape(&self.rest()[..end]);
+        self.pos += end;
+        element.text += text.as_str();
+      }
+    }
+  }
+}
+
+fn unescape(text: &str) -> String {
+  if !text.contains('&') {
+    return text.to_string();
+  }
+  let mut unescaped = String::with_capacity(text.len());
+  let mut rest = text;
+  while let Some(idx) = rest.find('&') {
+    unescaped += &rest[..idx];
+    rest = &rest[idx..];
+    let Some(end) = rest.find(';') else {
+      break;
+    };
+    let entity = &rest[1..end];
+    let c = match entity {
+      "lt" => Some('<'),
+      "gt" => Some('>'),
+      "amp" => Some('&'),
+      "quot" => Some('"'),
+      "apos" => Some('\''),
+      _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32),
+      _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+      _ => None,
+    };
+    match c {
+      Some(c) => {
+        unescaped.push(c);
+        rest = &rest[end + 1..];
+      }
+      None => {
+        unescaped.push('&');
+        rest = &rest[1..];
+      }
+    }
+  }
+  unescaped += rest;
+  unescaped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const VALGRIND: &str = include_str!("../tests/data/valgrind-memcheck.xml");
+  const GTEST: &str = include_str!("../tests/data/gtest-report.xml");
+
+  #[test]
+  fn parses_valgrind_report() {
+    let root = parse(VALGRIND).unwrap();
+    assert_eq!(root.name, "valgrindoutput");
+    assert_eq!(root.child_text("protocoltool"), Some("memcheck"));
+
+    let errors: Vec<&Element> = root.children("error").collect();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].child_text("kind"), Some("InvalidRead"));
+    let frame = errors[0].child("stack").unwrap().child("frame").unwrap();
+    assert_eq!(frame.child_text("fn"), Some("Buffer<int>::at(unsigned long) const"));
+    assert_eq!(frame.child_text("line"), Some("27"));
+    assert_eq!(errors[1].child("xwhat").unwrap().child_text("leakedbytes"), Some("40"));
+  }
+
+  #[test]
+  fn parses_gtest_report() {
+    let root = parse(GTEST).unwrap();
+    assert_eq!(root.attribute("tests"), Some("3"));
+
+    let suites: Vec<&Element> = root.children("testsuite").collect();
+    assert_eq!(suites.len(), 2);
+    let cases: Vec<&Element> = suites[0].children("testcase").collect();
+    assert_eq!(cases[0].attribute("name"), Some("ParsesNumbers"));
+    assert!(cases[0].child("failure").is_none());
+
+    let failure = cases[1].child("failure").unwrap();
+    assert!(failure.attribute("message").unwrap().contains("\n  parse(\"x<1\")\n"));
+    assert_eq!(failure.attribute("type"), Some(""));
+    assert!(failure.text.starts_with("/home/dev/demo/test/parser_test.cc:21\n"));
+    assert!(failure.text.contains("parse(\"x<1\")"));
+    assert_eq!(suites[1].child("testcase").unwrap().attribute("name"), Some("Grows/0"));
+  }
+
+  #[test]
+  fn rejects_truncated_reports() {
+    // What's left when the process writing the report is killed.
+    let cut = VALGRIND.find("<errorcounts>").unwrap();
+    assert!(parse(&VALGRIND[..cut]).is_none());
+    assert!(parse(&GTEST[..GTEST.len() / 2]).is_none());
+    assert!(parse("").is_none());
+    assert!(parse("<a><b></a></b>").is_none());
+  }
+
+  #[test]
+  fn skips_comments_and_instructions() {
+    let root = parse("<?xml version=\"1.0\"?>\n<!-- a -->\n<a x='1'><!-- b --><b/>text<?pi?></a>\n<!-- c -->\n").unwrap();
+    assert_eq!(root.attribute("x"), Some("1"));
+    assert_eq!(root.children.len(), 1);
+    assert_eq!(root.text, "text");
+  }
+
+  #[test]
+  fn unescapes_entities() {
+    assert_eq!(unescape("&lt;&gt;&amp;&quot;&apos;"), "<>&\"'");
+    assert_eq!(unescape("&#65;&#x42;&#x0A;"), "AB\n");
+    assert_eq!(unescape("a &nbsp; b & c"), "a &nbsp; b & c");
+    assert_eq!(unescape("&amp;lt;"), "&lt;");
+  }
+}